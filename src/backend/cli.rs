@@ -0,0 +1,129 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use super::{Backend, Branch};
+use crate::error::{Error, Result};
+use crate::utils;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Does everything by running the `git` binary, for when libgit2 can't cope with a repository.
+pub struct CliBackend {
+    git_dir: PathBuf,
+}
+
+impl CliBackend {
+    pub fn new(repo: &git2::Repository) -> Self {
+        CliBackend {
+            git_dir: repo.path().into(),
+        }
+    }
+
+    fn git(&self, args: &[&str]) -> Result<Vec<u8>> {
+        log::trace!("running git {}", args.join(" "));
+        let out = Command::new("git")
+            .arg("--git-dir")
+            .arg(&self.git_dir)
+            .args(args)
+            .output()?;
+        if !out.status.success() {
+            return Err(Error::Command(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(out.stdout)
+    }
+}
+
+fn bad_output<E: std::error::Error + Send + Sync + 'static>(e: E) -> Error {
+    Error::Backend(Box::new(e))
+}
+
+impl Backend for CliBackend {
+    fn branches(&self) -> Result<Vec<Branch>> {
+        // upstreams can be local or remote-tracking branches; we need their tips too
+        let mut tips = HashMap::new();
+        let out = self.git(&[
+            "for-each-ref",
+            "--format=%(refname)%00%(objectname)",
+            "refs/heads/",
+            "refs/remotes/",
+        ])?;
+        for line in out.split(|&c| c == b'\n') {
+            if let Some(i) = line.iter().position(|&c| c == 0) {
+                let oid = std::str::from_utf8(&line[i + 1..]).map_err(bad_output)?;
+                tips.insert(line[..i].to_vec(), git2::Oid::from_str(oid)?);
+            }
+        }
+
+        let mut branches = Vec::new();
+        let out = self.git(&[
+            "for-each-ref",
+            "--format=%(refname:lstrip=2)%00%(objectname)%00%(HEAD)%00%(committerdate:unix)\
+             %00%(authordate:unix)%00%(upstream)%00%(upstream:lstrip=2)%00%(contents:subject)",
+            "refs/heads/",
+        ])?;
+        for line in out.split(|&c| c == b'\n').filter(|l| !l.is_empty()) {
+            // only the ref names may not be valid UTF-8
+            let fields: Vec<&[u8]> = line.splitn(8, |&c| c == 0).collect();
+            if fields.len() != 8 {
+                return Err(Error::Command(format!(
+                    "unexpected for-each-ref output: {}",
+                    utils::escape_bytes(line)
+                )));
+            }
+            let upstream = tips
+                .get(fields[5])
+                .map(|oid| (utils::escape_bytes(fields[6]), *oid));
+            branches.push(Branch {
+                name: fields[0].to_vec(),
+                oid: git2::Oid::from_str(std::str::from_utf8(fields[1]).map_err(bad_output)?)?,
+                is_head: fields[2] == b"*",
+                time: std::str::from_utf8(fields[3])
+                    .map_err(bad_output)?
+                    .parse()
+                    .map_err(bad_output)?,
+                author_time: std::str::from_utf8(fields[4])
+                    .map_err(bad_output)?
+                    .parse()
+                    .map_err(bad_output)?,
+                summary: String::from_utf8_lossy(fields[7]).into(),
+                upstream,
+            });
+        }
+        Ok(branches)
+    }
+
+    fn count_ahead(
+        &self,
+        oid: git2::Oid,
+        base: git2::Oid,
+        first_parent: bool,
+        max: Option<usize>,
+    ) -> Result<(usize, bool)> {
+        let (tip, hidden) = (oid.to_string(), format!("^{}", base));
+        let mut args = vec!["rev-list", "--count", &tip, &hidden];
+        if first_parent {
+            args.push("--first-parent");
+        }
+        // ask for one more so we know if we hit the cap
+        let max_count = max.map(|m| format!("--max-count={}", m + 1));
+        if let Some(ref m) = max_count {
+            args.push(m);
+        }
+
+        let n: usize = String::from_utf8(self.git(&args)?)
+            .map_err(bad_output)?
+            .trim()
+            .parse()
+            .map_err(bad_output)?;
+        match max {
+            Some(max) if n > max => Ok((max, true)),
+            _ => Ok((n, false)),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use super::{Backend, Branch};
+use crate::error::{Error, Result};
+use crate::utils;
+use gix::remote::Direction;
+use std::convert::TryFrom;
+
+/// Everything in here can fail with a variety of gitoxide error types.
+type GixResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub struct GixBackend {
+    repo: gix::Repository,
+}
+
+impl GixBackend {
+    /// Open the same repository as `repo` with gitoxide.
+    pub fn open(repo: &git2::Repository) -> Result<Self> {
+        Ok(GixBackend {
+            repo: gix::open(repo.path()).map_err(|e| Error::Backend(Box::new(e)))?,
+        })
+    }
+
+    fn branches_impl(&self) -> GixResult<Vec<Branch>> {
+        let head_name = self.repo.head_name()?;
+
+        let mut branches = Vec::new();
+        for r in self.repo.references()?.local_branches()? {
+            let mut r = r?;
+            let commit = r.peel_to_commit()?;
+
+            let upstream = match self
+                .repo
+                .branch_remote_tracking_ref_name(r.name(), Direction::Fetch)
+            {
+                Some(tracking) => {
+                    let tracking = tracking?;
+                    match self.repo.try_find_reference(tracking.as_ref())? {
+                        Some(mut u) => Some((
+                            utils::escape_bytes(tracking.shorten()),
+                            to_oid(&u.peel_to_commit()?.id)?,
+                        )),
+                        None => None,
+                    }
+                }
+                None => None,
+            };
+
+            branches.push(Branch {
+                name: r.name().shorten().to_vec(),
+                oid: to_oid(&commit.id)?,
+                is_head: head_name.as_ref().is_some_and(|h| h.as_ref() == r.name()),
+                time: commit.time()?.seconds,
+                author_time: commit.author()?.time()?.seconds,
+                summary: commit.message()?.summary().to_string(),
+                upstream,
+            });
+        }
+        Ok(branches)
+    }
+
+    fn count_ahead_impl(
+        &self,
+        oid: git2::Oid,
+        base: git2::Oid,
+        first_parent: bool,
+        max: Option<usize>,
+    ) -> GixResult<(usize, bool)> {
+        let mut walk = self
+            .repo
+            .rev_walk([to_object_id(oid)?])
+            .with_hidden([to_object_id(base)?]);
+        if first_parent {
+            walk = walk.first_parent_only();
+        }
+
+        let max = max.unwrap_or(usize::MAX);
+        let mut n = 0;
+        for info in walk.all()? {
+            info?;
+            if n == max {
+                return Ok((n, true));
+            }
+            n += 1;
+        }
+        Ok((n, false))
+    }
+}
+
+fn to_oid(id: &gix::oid) -> GixResult<git2::Oid> {
+    Ok(git2::Oid::from_bytes(id.as_bytes())?)
+}
+
+fn to_object_id(oid: git2::Oid) -> GixResult<gix::ObjectId> {
+    Ok(gix::ObjectId::try_from(oid.as_bytes())?)
+}
+
+impl Backend for GixBackend {
+    fn branches(&self) -> Result<Vec<Branch>> {
+        self.branches_impl().map_err(Error::Backend)
+    }
+
+    fn count_ahead(
+        &self,
+        oid: git2::Oid,
+        base: git2::Oid,
+        first_parent: bool,
+        max: Option<usize>,
+    ) -> Result<(usize, bool)> {
+        self.count_ahead_impl(oid, base, first_parent, max)
+            .map_err(Error::Backend)
+    }
+}
@@ -0,0 +1,78 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use super::{Backend, Branch};
+use crate::error::{Context, Result};
+use crate::utils;
+
+pub struct Libgit2Backend<'r> {
+    repo: &'r git2::Repository,
+}
+
+impl<'r> Libgit2Backend<'r> {
+    pub fn new(repo: &'r git2::Repository) -> Self {
+        Libgit2Backend { repo }
+    }
+}
+
+impl Backend for Libgit2Backend<'_> {
+    fn branches(&self) -> Result<Vec<Branch>> {
+        let mut branches = Vec::new();
+        for branch in self.repo.branches(Some(git2::BranchType::Local))? {
+            let (branch, _) = branch?;
+            let name = branch.name_bytes()?.to_vec();
+            let context = || format!("reading branch {}", utils::escape_bytes(&name));
+
+            let commit = branch.get().peel_to_commit().with_context(context)?;
+            let upstream = match branch.upstream() {
+                Ok(b) => Some((
+                    utils::escape_bytes(b.name_bytes()?),
+                    b.get().peel_to_commit().with_context(context)?.id(),
+                )),
+                Err(_) => None,
+            };
+
+            branches.push(Branch {
+                oid: commit.id(),
+                is_head: branch.is_head(),
+                time: commit.time().seconds(),
+                author_time: commit.author().when().seconds(),
+                summary: utils::commit_summary(&commit),
+                upstream,
+                name,
+            });
+        }
+        Ok(branches)
+    }
+
+    fn count_ahead(
+        &self,
+        oid: git2::Oid,
+        base: git2::Oid,
+        first_parent: bool,
+        max: Option<usize>,
+    ) -> Result<(usize, bool)> {
+        if !first_parent && max.is_none() {
+            return Ok((self.repo.graph_ahead_behind(oid, base)?.0, false));
+        }
+
+        let mut revwalk = self.repo.revwalk()?;
+        if first_parent {
+            revwalk.simplify_first_parent()?;
+        }
+        revwalk.push(oid)?;
+        revwalk.hide(base)?;
+
+        let max = max.unwrap_or(usize::MAX);
+        let mut n = 0;
+        for maybe_oid in revwalk {
+            maybe_oid?;
+            if n == max {
+                return Ok((n, true));
+            }
+            n += 1;
+        }
+        Ok((n, false))
+    }
+}
@@ -0,0 +1,60 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! The scanning layer, i.e. listing local branches and counting how far ahead they are, which is
+//! where most of the time goes in large repositories. Everything else goes through libgit2.
+
+use crate::error::Result;
+
+mod cli;
+#[cfg(feature = "gix")]
+mod gix;
+mod libgit2;
+
+/// The backends which can be selected with `--backend`.
+#[cfg(not(feature = "gix"))]
+pub const NAMES: &[&str] = &["libgit2", "cli"];
+#[cfg(feature = "gix")]
+pub const NAMES: &[&str] = &["libgit2", "cli", "gix"];
+
+/// A local branch, as found by a backend.
+pub struct Branch {
+    /// The short name, which may not be valid UTF-8.
+    pub name: Vec<u8>,
+    pub oid: git2::Oid,
+    pub is_head: bool,
+    /// Commit time of the tip, in seconds since the epoch.
+    pub time: i64,
+    /// Author time of the tip, which unlike the commit time survives rebases.
+    pub author_time: i64,
+    pub summary: String,
+    /// Short name (escaped for display) and tip of the upstream branch, if any.
+    pub upstream: Option<(String, git2::Oid)>,
+}
+
+pub trait Backend {
+    /// List all local branches.
+    fn branches(&self) -> Result<Vec<Branch>>;
+
+    /// Count the commits reachable from `oid` but not from `base`, stopping after `max` if given.
+    /// Also returns whether the count was capped.
+    fn count_ahead(
+        &self,
+        oid: git2::Oid,
+        base: git2::Oid,
+        first_parent: bool,
+        max: Option<usize>,
+    ) -> Result<(usize, bool)>;
+}
+
+/// Get the backend called `name` (one of `NAMES`) for `repo`.
+pub fn open<'r>(name: &str, repo: &'r git2::Repository) -> Result<Box<dyn Backend + 'r>> {
+    match name {
+        "libgit2" => Ok(Box::new(libgit2::Libgit2Backend::new(repo))),
+        "cli" => Ok(Box::new(cli::CliBackend::new(repo))),
+        #[cfg(feature = "gix")]
+        "gix" => Ok(Box::new(gix::GixBackend::open(repo)?)),
+        _ => unreachable!("unknown backend {}", name),
+    }
+}
@@ -0,0 +1,126 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::error::{Error, Result};
+use crate::utils;
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+
+/// Run git in `repo`, from where relative remote URLs are relative to.
+fn git(repo: &git2::Repository) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(repo.workdir().unwrap_or_else(|| repo.path()))
+        .arg("--git-dir")
+        .arg(repo.path());
+    cmd
+}
+
+/// Ask `remote` where the backups under `prefix` (e.g. `refs/backups/<host>/`) are, by branch.
+fn backed_up(
+    repo: &git2::Repository,
+    remote: &str,
+    prefix: &str,
+) -> Result<HashMap<String, git2::Oid>> {
+    let out = git(repo)
+        .args(["ls-remote", "--refs", remote])
+        .arg(format!("{}*", prefix))
+        .output()
+        .map_err(|e| Error::Command(format!("running git: {}", e)))?;
+    if !out.status.success() {
+        return Err(Error::Command(format!(
+            "git ls-remote {} failed: {}",
+            remote,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    let mut backups = HashMap::new();
+    for line in String::from_utf8_lossy(&out.stdout).lines() {
+        if let Some((oid, refname)) = line.split_once('\t') {
+            if let (Some(name), Ok(oid)) = (refname.strip_prefix(prefix), git2::Oid::from_str(oid))
+            {
+                backups.insert(name.to_string(), oid);
+            }
+        }
+    }
+    Ok(backups)
+}
+
+/// Push all the branches which aren't on any remote to `remote` under
+/// `refs/backups/<host>/<branch>`, unless they're backed up as is already, or only print what
+/// would be pushed if `dry_run`.
+pub fn backup(repo: &git2::Repository, remote: &str, dry_run: bool) -> Result<()> {
+    // error out early on typos rather than have git take it as a URL
+    repo.find_remote(remote)?;
+
+    let host = gethostname::gethostname();
+    let host = match host.to_str() {
+        Some(host) if !host.is_empty() => host.to_string(),
+        _ => return Err(Error::NotFound("no usable hostname".into())),
+    };
+
+    let prefix = format!("refs/backups/{}/", host);
+    let backups = backed_up(repo, remote, &prefix)?;
+
+    let tips = crate::remote_tips(repo)?;
+    let shallow = crate::shallow_roots(repo)?;
+    let mut reachable = HashSet::new();
+    if !shallow.is_empty() {
+        for &tip in &tips {
+            reachable.extend(crate::shallow_ancestors(repo, tip, false, &shallow)?);
+        }
+    }
+
+    let mut refspecs = Vec::new();
+    for maybe_branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = maybe_branch?;
+        let oid = match branch.get().target() {
+            Some(oid) => oid,
+            None => continue,
+        };
+        let pushed = if shallow.is_empty() {
+            crate::is_reachable(repo, oid, &tips)?
+        } else {
+            reachable.contains(&oid)
+        };
+        if pushed {
+            continue;
+        }
+
+        // refspecs go through the command line, which we keep to UTF-8
+        let name = match std::str::from_utf8(branch.name_bytes()?) {
+            Ok(name) => name.to_string(),
+            Err(_) => {
+                eprintln!(
+                    "skipping {}: name is not valid UTF-8",
+                    utils::escape_bytes(branch.name_bytes()?)
+                );
+                continue;
+            }
+        };
+        let dest = format!("{}{}", prefix, name);
+        if backups.get(&name) == Some(&oid) {
+            println!("{} -> {} is up to date", name, dest);
+            continue;
+        }
+        println!(
+            "{} {} -> {}",
+            if dry_run { "would push" } else { "pushing" },
+            name,
+            dest
+        );
+        // force, since branches get rebased and the backup should just follow
+        refspecs.push(format!("+refs/heads/{}:{}", name, dest));
+    }
+
+    if dry_run || refspecs.is_empty() {
+        return Ok(());
+    }
+
+    let status = git(repo).arg("push").arg(remote).args(&refspecs).status()?;
+    if !status.success() {
+        return Err(Error::Command(format!("git push {} failed", remote)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Long-lived branches like `develop` or `release/*` which other branches are compared against
+//! instead of the default branch, from `bstatus.bases`.
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::pattern;
+use log::debug;
+use std::cmp::Reverse;
+
+pub struct Base {
+    /// The branch name, e.g. `develop`, or `origin/develop` if there's no local branch.
+    pub name: String,
+    pub refname: String,
+    pub oid: git2::Oid,
+}
+
+impl Base {
+    /// Whether this is `branch` itself, or its counterpart on a remote.
+    fn is(&self, branch: &str) -> bool {
+        match self.refname.strip_prefix("refs/remotes/") {
+            Some(r) => r.split_once('/').map(|(_, b)| b) == Some(branch),
+            None => self.name == branch,
+        }
+    }
+}
+
+/// Resolve branch names or globs like `release/*` to the local branches they match, or if there
+/// aren't any, the remote-tracking branches.
+pub fn resolve(repo: &git2::Repository, patterns: &[String]) -> Result<Vec<Base>> {
+    let remotes = repo.remotes()?;
+    let mut bases: Vec<Base> = Vec::new();
+    for pattern in patterns {
+        let mut globs = vec![format!("refs/heads/{}", pattern)];
+        globs.extend(
+            remotes
+                .iter()
+                .flatten()
+                .map(|remote| format!("refs/remotes/{}/{}", remote, pattern)),
+        );
+        for glob in globs {
+            let mut found = false;
+            for maybe_ref in repo.references_glob(&glob)? {
+                let r = maybe_ref?;
+                let (refname, oid) = match (r.name(), r.resolve()?.target()) {
+                    (Some(refname), Some(oid)) => (refname.to_string(), oid),
+                    _ => continue,
+                };
+                // e.g. origin/HEAD
+                if refname.ends_with("/HEAD") {
+                    continue;
+                }
+                found = true;
+                if !bases.iter().any(|b| b.refname == refname) {
+                    let name = refname
+                        .strip_prefix("refs/heads/")
+                        .or_else(|| refname.strip_prefix("refs/remotes/"))
+                        .unwrap_or(&refname)
+                        .to_string();
+                    bases.push(Base { name, refname, oid });
+                }
+            }
+            if found {
+                break;
+            }
+        }
+    }
+    Ok(bases)
+}
+
+/// Get the bases declared in `bstatus.bases`, if any.
+pub fn declared(repo: &git2::Repository, config: &git2::Config) -> Result<Vec<Base>> {
+    let patterns = config::list(config, config::BASES)?;
+    let bases = resolve(repo, &patterns)?;
+    if !patterns.is_empty() {
+        let names: Vec<&str> = bases.iter().map(|b| b.name.as_str()).collect();
+        debug!("declared bases: {}", names.join(", "));
+    }
+    Ok(bases)
+}
+
+/// Branches matching a pattern, compared against the given bases rather than all of them, from
+/// `bstatus.<pattern>.base`.
+pub struct Mapping {
+    pattern: String,
+    bases: Vec<Base>,
+}
+
+/// Get the `bstatus.<pattern>.base` settings, in the order they're set. The base can be a glob
+/// too, e.g. `release/*`, in which case the nearest one is used.
+pub fn mappings(repo: &git2::Repository, config: &git2::Config) -> Result<Vec<Mapping>> {
+    let mut mappings = Vec::new();
+    for (pattern, base, _) in config::per_pattern(config, config::PATTERN_BASE)? {
+        let base = match String::from_utf8(base) {
+            Ok(base) => base,
+            Err(_) => continue,
+        };
+        let bases = resolve(repo, std::slice::from_ref(&base))?;
+        if bases.is_empty() {
+            let key = config::PATTERN_BASE.replace("<pattern>", &pattern);
+            return Err(Error::NotFound(format!("{}: {} not found", key, base)));
+        }
+        mappings.push(Mapping { pattern, bases });
+    }
+    Ok(mappings)
+}
+
+/// Get the bases of the first mapping whose pattern `branch` matches, if any.
+pub fn mapped<'a>(mappings: &'a [Mapping], branch: &str) -> Option<&'a [Base]> {
+    mappings
+        .iter()
+        .find(|m| pattern::glob(&m.pattern, branch))
+        .map(|m| m.bases.as_slice())
+}
+
+/// Find the base `branch` (at `oid`) is closest to: the one it has the fewest commits on top of,
+/// and if there's a tie, the one it forked off most recently.
+pub fn nearest<'a>(
+    repo: &git2::Repository,
+    bases: &'a [Base],
+    branch: &str,
+    oid: git2::Oid,
+) -> Result<Option<&'a Base>> {
+    let mut best: Option<((usize, Reverse<i64>), &Base)> = None;
+    for base in bases {
+        if base.is(branch) {
+            continue;
+        }
+        let merge_base = match repo.merge_base(oid, base.oid) {
+            Ok(mb) => mb,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let (ahead, _) = repo.graph_ahead_behind(oid, base.oid)?;
+        let time = repo.find_commit(merge_base)?.time().seconds();
+        let key = (ahead, Reverse(time));
+        if best.is_none_or(|(k, _)| key < k) {
+            best = Some((key, base));
+        }
+    }
+    if let Some((_, base)) = best {
+        debug!("{}: nearest declared base is {}", branch, base.name);
+    }
+    Ok(best.map(|(_, base)| base))
+}
@@ -0,0 +1,275 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! What the messages of the commits on a branch say about it.
+
+use crate::config;
+use crate::error::{Error, Result};
+use regex::Regex;
+
+/// Issue references like `#123` or `JIRA-456`, unless `bstatus.issue-pattern` says otherwise.
+const ISSUE_PATTERN: &str = r"\B#\d+\b|\b[A-Z][A-Z0-9]+-\d+\b";
+
+/// Get the messages of the commits reachable from `oid` but not from `base`, newest first,
+/// stopping after `max`.
+pub fn messages(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    base: git2::Oid,
+    first_parent: bool,
+    max: Option<usize>,
+) -> Result<Vec<String>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(oid)?;
+    revwalk.hide(base)?;
+    if first_parent {
+        revwalk.simplify_first_parent()?;
+    }
+    let mut messages = Vec::new();
+    for maybe_oid in revwalk.take(max.unwrap_or(usize::MAX)) {
+        let oid = match maybe_oid {
+            Ok(oid) => oid,
+            // the walk fails at the edge of a shallow clone; that's all there is
+            Err(e) if e.code() == git2::ErrorCode::NotFound && repo.is_shallow() => break,
+            Err(e) => return Err(e.into()),
+        };
+        let commit = repo.find_commit(oid)?;
+        messages.push(String::from_utf8_lossy(commit.message_bytes()).into_owned());
+    }
+    Ok(messages)
+}
+
+/// Get the regex matching issue references, from `bstatus.issue-pattern` if it's set.
+pub fn issue_pattern(config: &git2::Config) -> Result<Regex> {
+    let pattern = match config.get_string(config::ISSUE_PATTERN) {
+        Ok(pattern) => pattern,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => ISSUE_PATTERN.into(),
+        Err(e) => return Err(e.into()),
+    };
+    Regex::new(&pattern).map_err(|e| Error::BadConfig {
+        key: config::ISSUE_PATTERN.into(),
+        reason: e.to_string(),
+        value: pattern,
+    })
+}
+
+/// Get the distinct issues referenced in `messages`, oldest first.
+pub fn issues(messages: &[String], pattern: &Regex) -> Vec<String> {
+    let mut issues: Vec<String> = Vec::new();
+    for message in messages.iter().rev() {
+        for m in pattern.find_iter(message) {
+            if !issues.iter().any(|i| i == m.as_str()) {
+                issues.push(m.as_str().into());
+            }
+        }
+    }
+    issues
+}
+
+/// Get the trailers to show from `bstatus.trailers`, which can be given more than once or as a
+/// comma-separated list. Empty means all of them.
+pub fn trailer_keys(config: &git2::Config) -> Result<Vec<String>> {
+    Ok(config::list(config, config::TRAILERS)?
+        .into_iter()
+        .map(|key| key.trim_end_matches(':').to_string())
+        .filter(|key| !key.is_empty())
+        .collect())
+}
+
+/// Collect the distinct values of the trailers called any of `keys` (or all of them if empty) in
+/// `messages`, grouped by key. Keys are matched ignoring case, and listed in the order given, or
+/// the order they first show up in otherwise. Values are oldest first.
+pub fn trailers(messages: &[String], keys: &[String]) -> Vec<(String, Vec<String>)> {
+    let mut found: Vec<(String, Vec<String>)> =
+        keys.iter().map(|k| (k.clone(), Vec::new())).collect();
+    for message in messages.iter().rev() {
+        let parsed = match git2::message_trailers_strs(message) {
+            Ok(parsed) => parsed,
+            Err(_) => continue,
+        };
+        for (key, value) in parsed.iter() {
+            let value = value.trim();
+            let i = match found.iter().position(|(k, _)| k.eq_ignore_ascii_case(key)) {
+                Some(i) => i,
+                None if keys.is_empty() => {
+                    found.push((key.to_string(), Vec::new()));
+                    found.len() - 1
+                }
+                None => continue,
+            };
+            if !found[i].1.iter().any(|v| v == value) {
+                found[i].1.push(value.to_string());
+            }
+        }
+    }
+    found.retain(|(_, values)| !values.is_empty());
+    found
+}
+
+/// The version bump a branch calls for, going by semver and its conventional commits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl Bump {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Bump::Patch => "patch",
+            Bump::Minor => "minor",
+            Bump::Major => "major",
+        }
+    }
+}
+
+/// Get the type of a conventional commit, i.e. `fix` in `fix(parser)!: handle nulls`, and whether
+/// it's marked as breaking with a `!` there.
+fn conventional(message: &str) -> Option<(String, bool)> {
+    let subject = message.lines().next()?;
+    let (prefix, _) = subject.split_once(": ")?;
+    let (prefix, bang) = match prefix.strip_suffix('!') {
+        Some(prefix) => (prefix, true),
+        None => (prefix, false),
+    };
+    let kind = match prefix.find('(') {
+        Some(i) if prefix.ends_with(')') => &prefix[..i],
+        Some(_) => return None,
+        None => prefix,
+    };
+    if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some((kind.to_ascii_lowercase(), bang))
+}
+
+/// Whether a commit breaks compatibility: a `!` after its type, or a `BREAKING CHANGE:` footer.
+fn breaking(message: &str) -> bool {
+    matches!(conventional(message), Some((_, true)))
+        || message
+            .lines()
+            .skip(1)
+            .any(|l| l.starts_with("BREAKING CHANGE:") || l.starts_with("BREAKING-CHANGE:"))
+}
+
+/// Estimate the version bump `messages` call for: major if any of them is breaking, minor if any
+/// is a `feat`, and patch otherwise. None if there aren't any.
+pub fn bump(messages: &[String]) -> Option<Bump> {
+    messages
+        .iter()
+        .map(|message| {
+            if breaking(message) {
+                Bump::Major
+            } else if matches!(conventional(message), Some((ref kind, _)) if kind == "feat") {
+                Bump::Minor
+            } else {
+                Bump::Patch
+            }
+        })
+        .max()
+}
+
+/// Count the conventional-commit types in `messages`, in the order they first show up, oldest
+/// first. Commits which don't follow the convention count as `other`, at the end.
+pub fn types(messages: &[String]) -> Vec<(String, usize)> {
+    let mut types: Vec<(String, usize)> = Vec::new();
+    let mut other = 0;
+    for message in messages.iter().rev() {
+        match conventional(message) {
+            Some((kind, _)) => match types.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, n)) => *n += 1,
+                None => types.push((kind, 1)),
+            },
+            None => other += 1,
+        }
+    }
+    if other > 0 {
+        types.push(("other".into(), other));
+    }
+    types
+}
+
+#[test]
+fn test_types() {
+    assert_eq!(Some(("feat".into(), false)), conventional("feat: x"));
+    assert_eq!(
+        Some(("fix".into(), true)),
+        conventional("Fix(parser)!: x\n\nbody")
+    );
+    assert_eq!(None, conventional("Merge branch 'a': b"));
+    assert_eq!(None, conventional("feat(oops: x"));
+    assert_eq!(None, conventional("just a subject"));
+    let messages: Vec<String> = ["docs: d", "wip", "feat: b", "fix: c", "feat(ui): a"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(
+        vec![
+            ("feat".to_string(), 2),
+            ("fix".to_string(), 1),
+            ("docs".to_string(), 1),
+            ("other".to_string(), 1),
+        ],
+        types(&messages)
+    );
+}
+
+#[test]
+fn test_bump() {
+    let bump_of = |messages: &[&str]| {
+        let messages: Vec<String> = messages.iter().map(|s| s.to_string()).collect();
+        bump(&messages)
+    };
+    assert_eq!(None, bump_of(&[]));
+    assert_eq!(Some(Bump::Patch), bump_of(&["fix: a", "wip"]));
+    assert_eq!(Some(Bump::Minor), bump_of(&["fix: a", "feat(ui): b"]));
+    assert_eq!(Some(Bump::Major), bump_of(&["feat: a", "fix!: b"]));
+    assert_eq!(
+        Some(Bump::Major),
+        bump_of(&["chore: a\n\nBREAKING CHANGE: drops the config file"])
+    );
+}
+
+#[test]
+fn test_trailers() {
+    let messages = vec![
+        "b\n\nTicket: T-2\nReviewed-by: Bob\n".to_string(),
+        "a\n\nreviewed-by: Alice\nTicket: T-1\nSigned-off-by: Me\n".to_string(),
+    ];
+    assert_eq!(
+        vec![
+            (
+                "Reviewed-by".to_string(),
+                vec!["Alice".to_string(), "Bob".to_string()]
+            ),
+            (
+                "Ticket".to_string(),
+                vec!["T-1".to_string(), "T-2".to_string()]
+            ),
+        ],
+        trailers(
+            &messages,
+            &["Reviewed-by".into(), "Ticket".into(), "Acked-by".into()]
+        )
+    );
+    let all: Vec<String> = trailers(&messages, &[])
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(vec!["reviewed-by", "Ticket", "Signed-off-by"], all);
+}
+
+#[test]
+fn test_issues() {
+    let pattern = Regex::new(ISSUE_PATTERN).unwrap();
+    let messages = vec![
+        "fix: more of it\n\nSee #12 and ABC-3.".to_string(),
+        "feat: do the thing (#12)\n\nFixes JIRA-456, not a#1 or abc-3.".to_string(),
+    ];
+    assert_eq!(
+        vec!["#12", "JIRA-456", "ABC-3"],
+        issues(&messages, &pattern)
+    );
+}
@@ -0,0 +1,145 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::error::{Context, Error, Result};
+use crate::utils;
+use ansi_term::Colour;
+
+/// Resolve `name` (a branch, tag, or anything else git understands) to a commit.
+fn resolve<'r>(repo: &'r git2::Repository, name: &str) -> Result<git2::Commit<'r>> {
+    match repo.revparse_single(name) {
+        Ok(obj) => Ok(obj
+            .peel_to_commit()
+            .with_context(|| format!("resolving {}", name))?),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            Err(Error::NotFound(format!("{} not found", name)))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get the commits reachable from `oid` but not from `hide`, newest first, stopping after `max`.
+/// Also returns whether the walk was cut short, either by `max` or by the edge of a shallow clone.
+pub fn only_in(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    hide: git2::Oid,
+    max: Option<usize>,
+) -> Result<(Vec<git2::Oid>, bool)> {
+    let mut revwalk = repo.revwalk()?;
+    if !repo.is_shallow() {
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+    }
+    revwalk.push(oid)?;
+    revwalk.hide(hide)?;
+    let max = max.unwrap_or(usize::MAX);
+    let mut oids = Vec::new();
+    for maybe_oid in revwalk {
+        match maybe_oid {
+            Ok(_) if oids.len() == max => return Ok((oids, true)),
+            Ok(oid) => oids.push(oid),
+            Err(e) if e.code() == git2::ErrorCode::NotFound && repo.is_shallow() => {
+                return Ok((oids, true))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok((oids, false))
+}
+
+/// Print how far apart `a` and `b` are: the commits only on either side, where they forked, and
+/// how much their trees differ.
+pub fn compare(
+    repo: &git2::Repository,
+    a: &str,
+    b: &str,
+    max_ahead: Option<usize>,
+    plain: bool,
+) -> Result<()> {
+    let (commit_a, commit_b) = (resolve(repo, a)?, resolve(repo, b)?);
+    let (only_a, capped_a) = only_in(repo, commit_a.id(), commit_b.id(), max_ahead)
+        .with_context(|| format!("listing commits on {}", a))?;
+    let (only_b, capped_b) = only_in(repo, commit_b.id(), commit_a.id(), max_ahead)
+        .with_context(|| format!("listing commits on {}", b))?;
+
+    let green = utils::style(plain, Colour::Green);
+    let count = |n: usize, capped: bool| {
+        let s = utils::plural("commit", n as u64);
+        if capped {
+            format!("at least {}", s)
+        } else {
+            s
+        }
+    };
+    println!(
+        "{} only in {}, {} only in {}",
+        green.paint(count(only_a.len(), capped_a)),
+        a,
+        green.paint(count(only_b.len(), capped_b)),
+        b
+    );
+
+    match repo.merge_base(commit_a.id(), commit_b.id()) {
+        Ok(base) => {
+            let base = repo.find_commit(base)?;
+            let age = utils::epoch_to_relative_str(base.time().seconds().max(0) as u64);
+            println!(
+                "Common base: {:.8} {} ({})",
+                base.id(),
+                utils::commit_summary(&base),
+                if age == "now" {
+                    "just now".into()
+                } else {
+                    format!("{} ago", age)
+                }
+            );
+        }
+        Err(e) if e.code() == git2::ErrorCode::NotFound => println!("No common base"),
+        // the fork is cut off in a shallow clone
+        Err(_) if repo.is_shallow() => println!("Common base not in shallow clone"),
+        Err(e) => return Err(e.into()),
+    }
+
+    let diff = repo
+        .diff_tree_to_tree(Some(&commit_b.tree()?), Some(&commit_a.tree()?), None)
+        .with_context(|| format!("diffing {} and {}", a, b))?;
+    match diff.stats() {
+        Ok(stats) => println!(
+            "{} changed, {}(+), {}(-)",
+            utils::plural("file", stats.files_changed() as u64),
+            utils::plural("insertion", stats.insertions() as u64),
+            utils::plural("deletion", stats.deletions() as u64)
+        ),
+        // counting lines needs the blobs, which a partial clone may not have; their names will do
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            println!(
+                "{} changed (contents not fetched):",
+                utils::plural("file", diff.deltas().len() as u64)
+            );
+            for delta in diff.deltas() {
+                let file = delta.new_file().path().or_else(|| delta.old_file().path());
+                if let Some(path) = file {
+                    println!("    {}", path.display());
+                }
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    for (name, oids, capped) in &[(a, &only_a, capped_a), (b, &only_b, capped_b)] {
+        if oids.is_empty() {
+            continue;
+        }
+        println!("\nOnly in {}:", name);
+        for &oid in oids.iter() {
+            let commit = repo.find_commit(oid)?;
+            println!("    {:.8} {}", oid, utils::commit_summary(&commit));
+        }
+        if *capped {
+            println!("    ...");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::error::{Error, Result};
+use crate::utils;
+
+pub const DEFAULT_BASE: &str = "bstatus.default-base";
+pub const BASES: &str = "bstatus.bases";
+/// One per pattern, e.g. `bstatus.release/*.base`.
+pub const PATTERN_BASE: &str = "bstatus.<pattern>.base";
+pub const HUMAN_SECTIONS: &str = "bstatus.human-sections";
+pub const STALE_AFTER: &str = "bstatus.stale-after";
+pub const HINTS: &str = "bstatus.hints";
+pub const NUMBERS: &str = "bstatus.numbers";
+pub const TRAILERS: &str = "bstatus.trailers";
+pub const ISSUE_PATTERN: &str = "bstatus.issue-pattern";
+pub const FORGE: &str = "bstatus.forge";
+pub const FORGE_CACHE_TTL: &str = "bstatus.forge-cache-ttl";
+pub const GITEA_TOKEN: &str = "bstatus.gitea-token";
+pub const GERRIT_URL: &str = "bstatus.gerrit-url";
+
+/// The git config settings which change what we print, and what they're used for. Anything
+/// reading one of ours should go through the constants above, so this stays complete.
+const SETTINGS: &[(&str, &str)] = &[
+    (DEFAULT_BASE, "branch to compare against"),
+    (BASES, "branches to compare against the nearest of"),
+    (PATTERN_BASE, "base for branches matching <pattern>"),
+    (HUMAN_SECTIONS, "sections of the default output"),
+    (STALE_AFTER, "age of a stale branch"),
+    (HINTS, "hints in the default output"),
+    (NUMBERS, "[N] next to each row, for expand"),
+    (TRAILERS, "trailers for --trailers"),
+    (ISSUE_PATTERN, "issue references for --issues"),
+    (FORGE, "forge for --prs and --ci"),
+    (FORGE_CACHE_TTL, "how long forge answers are cached"),
+    (GITEA_TOKEN, "token for Gitea and Forgejo"),
+    (GERRIT_URL, "Gerrit server for --gerrit"),
+    ("init.defaultBranch", "fallback default branch"),
+    ("user.email", "who --mine matches"),
+    ("mailmap.file", "identities for --author and --mine"),
+    ("mailmap.blob", "identities for --author and --mine"),
+];
+
+/// Settings which can be given more than once, see `list()`.
+const LISTS: &[&str] = &[BASES, HUMAN_SECTIONS, TRAILERS];
+
+/// Settings whose values shouldn't end up on screen.
+const SECRETS: &[&str] = &[GITEA_TOKEN];
+
+/// Get a setting which can be given more than once, or as a comma-separated list, or both.
+pub fn list(config: &git2::Config, name: &str) -> Result<Vec<String>> {
+    let mut values = Vec::new();
+    let mut entries = config.multivar(name, None)?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let value = String::from_utf8_lossy(entry.value_bytes()).into_owned();
+        values.extend(
+            value
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+        );
+    }
+    Ok(values)
+}
+
+/// Get the entries of a per-pattern setting like `PATTERN_BASE` as the pattern, the value and the
+/// level it was set at, in the order they're set.
+pub fn per_pattern(
+    config: &git2::Config,
+    setting: &str,
+) -> Result<Vec<(String, Vec<u8>, git2::ConfigLevel)>> {
+    let (prefix, suffix) = setting.split_once("<pattern>").unwrap();
+    let regex = format!("^{}.*{}$", regex::escape(prefix), regex::escape(suffix));
+    let mut found = Vec::new();
+    let mut entries = config.entries(Some(&regex))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let pattern = match entry.name() {
+            Some(key) => key[prefix.len()..key.len() - suffix.len()].to_string(),
+            None => continue,
+        };
+        found.push((pattern, entry.value_bytes().to_vec(), entry.level()));
+    }
+    Ok(found)
+}
+
+fn level_name(level: git2::ConfigLevel) -> &'static str {
+    match level {
+        git2::ConfigLevel::ProgramData => "programdata",
+        git2::ConfigLevel::System => "system",
+        git2::ConfigLevel::XDG => "xdg",
+        git2::ConfigLevel::Global => "global",
+        git2::ConfigLevel::Local => "local",
+        git2::ConfigLevel::App => "app",
+        git2::ConfigLevel::Highest => "command line",
+    }
+}
+
+/// Print every setting we read, its effective value and the config file level it came from,
+/// followed by `BSTATUS_LOG` and the default branch this all resolves to. Command-line flags
+/// still override these per run, and aren't shown.
+pub fn show(repo: &git2::Repository, plain: bool) -> Result<()> {
+    let config = repo.config()?.snapshot()?;
+    let mut rows = Vec::new();
+    let unset = |name: &str, what: &str| (name.to_string(), "-".into(), format!("unset; {}", what));
+    for &(name, what) in SETTINGS {
+        let shown = |value: &[u8]| {
+            if SECRETS.contains(&name) {
+                "(hidden)".into()
+            } else {
+                utils::escape_bytes(value)
+            }
+        };
+        if name.contains("<pattern>") {
+            let entries = per_pattern(&config, name)?;
+            if entries.is_empty() {
+                rows.push(unset(name, what));
+            }
+            for (pattern, value, level) in entries {
+                rows.push((
+                    name.replace("<pattern>", &pattern),
+                    shown(&value),
+                    format!("{}; {}", level_name(level), what),
+                ));
+            }
+            continue;
+        }
+        // all of a list, or whichever value wins otherwise
+        let mut values = Vec::new();
+        let mut level = None;
+        let mut entries = config.multivar(name, None)?;
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            if !LISTS.contains(&name) {
+                values.clear();
+            }
+            values.push(shown(entry.value_bytes()));
+            level = Some(entry.level());
+        }
+        match level {
+            Some(level) => rows.push((
+                name.to_string(),
+                values.join(", "),
+                format!("{}; {}", level_name(level), what),
+            )),
+            None => rows.push(unset(name, what)),
+        }
+    }
+
+    let what = "log filters, as with --debug";
+    match std::env::var("BSTATUS_LOG") {
+        Ok(value) => rows.push((
+            "BSTATUS_LOG".into(),
+            utils::escape_bytes(value.as_bytes()),
+            format!("environment; {}", what),
+        )),
+        Err(_) => rows.push(unset("BSTATUS_LOG", what)),
+    }
+
+    let default = match crate::find_default_branch(repo, false) {
+        Ok((_, Some(name))) => name
+            .strip_prefix(crate::LOCAL_BRANCH_REF_PREFIX)
+            .unwrap_or(&name)
+            .to_string(),
+        Ok((oid, None)) => format!("{:.8}", oid),
+        Err(Error::NotFound(_)) => "-".into(),
+        Err(e) => return Err(e),
+    };
+    rows.push(("default branch".into(), default, "detected".into()));
+
+    let (name_width, value_width) = if plain {
+        (0, 0)
+    } else {
+        (
+            rows.iter().map(|r| r.0.len()).max().unwrap(),
+            rows.iter().map(|r| r.1.chars().count()).max().unwrap(),
+        )
+    };
+    for (name, value, source) in &rows {
+        println!(
+            "{:name_width$} {:value_width$} ({})",
+            name,
+            value,
+            source,
+            name_width = name_width,
+            value_width = value_width
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::error::{Error, Result};
+use std::process::Command;
+
+/// Find the editor the way git does: `GIT_EDITOR`, `core.editor`, `VISUAL`, `EDITOR`, then vi.
+fn editor(config: &git2::Config) -> Result<String> {
+    if let Ok(editor) = std::env::var("GIT_EDITOR") {
+        return Ok(editor);
+    }
+    match config.get_string("core.editor") {
+        Ok(editor) => return Ok(editor),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into()))
+}
+
+/// Edit `branch.<name>.description` in the user's editor, like `git branch --edit-description`.
+/// Lines starting with `#` are dropped, and an empty description unsets it.
+pub fn describe(repo: &git2::Repository, name: &str) -> Result<()> {
+    match repo.find_branch(name, git2::BranchType::Local) {
+        Ok(_) => {}
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            return Err(Error::NotFound(format!("branch {} not found", name)))
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+    let key = format!("branch.{}.description", name);
+    let current = match config.get_string(&key) {
+        Ok(description) => description,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    // same file git uses, so editors configured for it do the right thing
+    let path = repo.path().join("EDIT_DESCRIPTION");
+    std::fs::write(
+        &path,
+        format!(
+            "{}\n# Please edit the description for the branch\n#   {}\n\
+             # Lines starting with '#' will be stripped.\n",
+            current.trim_end(),
+            name
+        ),
+    )?;
+
+    // the editor setting is a shell snippet, e.g. "code --wait"
+    let editor = editor(&repo.config()?)?;
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(&path)
+        .status()
+        .map_err(|e| Error::Command(format!("running {}: {}", editor, e)))?;
+    if !status.success() {
+        return Err(Error::Command(format!("{} failed: {}", editor, status)));
+    }
+
+    let edited = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    let description: Vec<&str> = edited.lines().filter(|l| !l.starts_with('#')).collect();
+    let description = description.join("\n").trim().to_string();
+
+    if description.is_empty() {
+        match config.remove(&key) {
+            Ok(()) => {}
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        // git keeps the trailing newline
+        config.set_str(&key, &format!("{}\n", description))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Errors, and the exit codes they map to.
+
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("not a git repository: {}", .0.display())]
+    NotARepo(PathBuf),
+    /// Something asked for (a branch, a tag, ...) doesn't exist.
+    #[error("{0}")]
+    NotFound(String),
+    /// Something exists but isn't here, e.g. objects left out of a partial clone.
+    #[error("{0}")]
+    NotLocal(String),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// A setting has a value we can't make sense of.
+    #[error("bad value '{value}' for {key}: {reason}")]
+    BadConfig {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    /// Running an external command failed.
+    #[error("{0}")]
+    Command(String),
+    /// An error from a backend which doesn't go through libgit2.
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+    #[error("{context}: {source}")]
+    Context { context: String, source: Box<Error> },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The process exit code for this error. 1 is left for usage errors and failed checks.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Context { source, .. } => source.exit_code(),
+            // same as git itself
+            Error::NotARepo(_) => 128,
+            Error::BadConfig { .. } => 1,
+            Error::NotFound(_) => 2,
+            Error::Git(e) if e.code() == git2::ErrorCode::NotFound => 2,
+            Error::Git(_) | Error::Backend(_) | Error::NotLocal(_) => 3,
+            Error::Io(_) => 4,
+            Error::Command(_) => 5,
+        }
+    }
+}
+
+/// Attach a description of what was being done (e.g. which branch was being looked at) to errors.
+pub trait Context<T> {
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T> {
+        self.map_err(|e| Error::Context {
+            context: f().into(),
+            source: Box::new(e.into()),
+        })
+    }
+}
@@ -0,0 +1,187 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Remembering what forges said about each branch tip in `.git/bstatus/forge-cache.json`, so
+//! running again doesn't eat into API rate limits, and still works offline.
+
+use super::{CiStatus, Forge, Head, PullRequest, State};
+use crate::error::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Entries older than this are dropped altogether, rather than kept for offline use.
+const MAX_AGE: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// When we asked, in seconds since the epoch.
+    time: u64,
+    #[serde(default)]
+    pull_requests: Vec<CachedPullRequest>,
+    /// For CI status lookups, the status if there was one.
+    #[serde(default)]
+    ci: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedPullRequest {
+    number: u64,
+    state: String,
+    url: String,
+    head: Option<String>,
+}
+
+/// A forge whose answers are cached for `ttl` seconds. Once they're stale, they're still used if
+/// the forge can't be reached.
+pub struct Cached {
+    forge: Box<dyn Forge>,
+    path: PathBuf,
+    ttl: u64,
+    now: u64,
+    entries: RefCell<HashMap<String, Entry>>,
+    dirty: RefCell<bool>,
+}
+
+impl Cached {
+    pub fn new(forge: Box<dyn Forge>, repo: &git2::Repository, ttl: u64) -> Self {
+        let path = repo.path().join("bstatus").join("forge-cache.json");
+        // a missing or corrupt cache is just an empty one
+        let entries = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Cached {
+            forge,
+            path,
+            ttl,
+            now: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            entries: RefCell::new(entries),
+            dirty: RefCell::new(false),
+        }
+    }
+
+    /// Get the entry for `key` if it's fresh, and otherwise ask `fetch`. If that fails, any
+    /// stale entry will do.
+    fn lookup<T>(
+        &self,
+        key: String,
+        what: &str,
+        fetch: impl FnOnce() -> Result<Entry>,
+        read: impl FnOnce(&Entry) -> T,
+    ) -> Result<T> {
+        let fresh = match self.entries.borrow().get(&key) {
+            Some(e) => self.now.saturating_sub(e.time) < self.ttl,
+            None => false,
+        };
+        if !fresh {
+            match fetch() {
+                Ok(entry) => {
+                    self.entries.borrow_mut().insert(key.clone(), entry);
+                    *self.dirty.borrow_mut() = true;
+                }
+                Err(err) if self.entries.borrow().contains_key(&key) => {
+                    debug!("{}: using stale cache entry: {}", what, err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(read(&self.entries.borrow()[&key]))
+    }
+
+    fn save(&self) -> Result<()> {
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|_, e| self.now.saturating_sub(e.time) < MAX_AGE);
+        std::fs::create_dir_all(self.path.parent().unwrap())?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&*entries).unwrap())?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for Cached {
+    fn drop(&mut self) {
+        if *self.dirty.borrow() {
+            if let Err(e) = self.save() {
+                debug!("couldn't save {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+fn to_state(s: &str) -> State {
+    match s {
+        "open" => State::Open,
+        "draft" => State::Draft,
+        "merged" => State::Merged,
+        _ => State::Closed,
+    }
+}
+
+fn to_ci_status(s: &str) -> Option<CiStatus> {
+    match s {
+        "success" => Some(CiStatus::Success),
+        "failure" => Some(CiStatus::Failure),
+        "pending" => Some(CiStatus::Pending),
+        _ => None,
+    }
+}
+
+impl Forge for Cached {
+    fn pull_requests(&self, head: &Head) -> Result<Vec<PullRequest>> {
+        let key = format!("{} {} {}", head.project.base, head.project.path, head.tip);
+        let fetch = || {
+            Ok(Entry {
+                time: self.now,
+                pull_requests: self
+                    .forge
+                    .pull_requests(head)?
+                    .iter()
+                    .map(|pr| CachedPullRequest {
+                        number: pr.number,
+                        state: pr.state.as_str().into(),
+                        url: pr.url.clone(),
+                        head: pr.head.map(|h| h.to_string()),
+                    })
+                    .collect(),
+                ci: None,
+            })
+        };
+        self.lookup(key, &head.branch, fetch, |e| {
+            e.pull_requests
+                .iter()
+                .map(|pr| PullRequest {
+                    number: pr.number,
+                    state: to_state(&pr.state),
+                    url: pr.url.clone(),
+                    head: pr.head.as_ref().and_then(|h| git2::Oid::from_str(h).ok()),
+                })
+                .collect()
+        })
+    }
+
+    fn ci_status(&self, head: &Head) -> Result<Option<CiStatus>> {
+        let key = format!(
+            "ci {} {} {}",
+            head.project.base, head.project.path, head.tip
+        );
+        let fetch = || {
+            Ok(Entry {
+                time: self.now,
+                pull_requests: Vec::new(),
+                ci: self.forge.ci_status(head)?.map(|s| s.as_str().into()),
+            })
+        };
+        self.lookup(key, &head.branch, fetch, |e| {
+            e.ci.as_deref().and_then(to_ci_status)
+        })
+    }
+}
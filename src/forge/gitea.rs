@@ -0,0 +1,133 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use super::{CiStatus, Forge, Head, Project, PullRequest, State};
+use crate::error::Result;
+use crate::http;
+use serde::Deserialize;
+use std::cell::RefCell;
+
+/// How many pull requests to ask for at once; the API caps it at 50 by default.
+const PAGE_SIZE: usize = 50;
+
+#[derive(Deserialize)]
+struct Pull {
+    number: u64,
+    state: String,
+    #[serde(default)]
+    merged: bool,
+    #[serde(default)]
+    draft: bool,
+    html_url: String,
+    head: PullHead,
+}
+
+#[derive(Deserialize)]
+struct PullHead {
+    #[serde(rename = "ref")]
+    branch: String,
+    sha: String,
+    /// Gone if the fork was deleted.
+    repo: Option<PullRepo>,
+}
+
+#[derive(Deserialize)]
+struct PullRepo {
+    full_name: String,
+}
+
+#[derive(Deserialize)]
+struct CombinedStatus {
+    state: String,
+    total_count: usize,
+}
+
+/// Gitea and its fork Forgejo, which share their API.
+pub struct Gitea {
+    project: Project,
+    token: Option<String>,
+    /// All the pull requests on the project, since they can't be looked up by branch.
+    pulls: RefCell<Option<Vec<Pull>>>,
+}
+
+impl Gitea {
+    pub fn new(project: Project, token: Option<String>) -> Self {
+        Gitea {
+            project,
+            token,
+            pulls: RefCell::new(None),
+        }
+    }
+
+    /// GET `path` under the repository's API.
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = format!(
+            "{}/api/v1/repos/{}/{}",
+            self.project.base, self.project.path, path
+        );
+        let auth = self.token.as_ref().map(|t| format!("token {}", t));
+        let mut headers = vec![("Accept", "application/json")];
+        if let Some(ref auth) = auth {
+            headers.push(("Authorization", auth));
+        }
+        super::parse("Gitea", &http::get(&url, &headers)?)
+    }
+
+    fn fetch(&self) -> Result<Vec<Pull>> {
+        let mut pulls = Vec::new();
+        for page in 1.. {
+            let batch: Vec<Pull> = self.get(&format!(
+                "pulls?state=all&limit={}&page={}",
+                PAGE_SIZE, page
+            ))?;
+            let done = batch.len() < PAGE_SIZE;
+            pulls.extend(batch);
+            if done {
+                break;
+            }
+        }
+        // newest first
+        pulls.sort_unstable_by_key(|p| std::cmp::Reverse(p.number));
+        Ok(pulls)
+    }
+}
+
+impl Forge for Gitea {
+    fn pull_requests(&self, head: &Head) -> Result<Vec<PullRequest>> {
+        let mut pulls = self.pulls.borrow_mut();
+        if pulls.is_none() {
+            *pulls = Some(self.fetch()?);
+        }
+        Ok(pulls
+            .iter()
+            .flatten()
+            .filter(|p| p.head.branch == head.branch)
+            .filter(|p| match p.head.repo {
+                Some(ref repo) => repo.full_name.eq_ignore_ascii_case(&head.project.path),
+                None => true,
+            })
+            .map(|p| PullRequest {
+                number: p.number,
+                state: match p.state.as_str() {
+                    _ if p.merged => State::Merged,
+                    "open" if p.draft => State::Draft,
+                    "open" => State::Open,
+                    _ => State::Closed,
+                },
+                url: p.html_url.clone(),
+                head: git2::Oid::from_str(&p.head.sha).ok(),
+            })
+            .collect())
+    }
+
+    fn ci_status(&self, head: &Head) -> Result<Option<CiStatus>> {
+        let status: CombinedStatus = self.get(&format!("commits/{}/status", head.tip))?;
+        Ok(match (status.total_count, status.state.as_str()) {
+            (0, _) => None,
+            (_, "success") => Some(CiStatus::Success),
+            (_, "pending") => Some(CiStatus::Pending),
+            _ => Some(CiStatus::Failure),
+        })
+    }
+}
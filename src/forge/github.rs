@@ -0,0 +1,142 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use super::{CiStatus, Forge, Head, Project, PullRequest, State};
+use crate::error::Result;
+use crate::http;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Pull {
+    number: u64,
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    merged_at: Option<String>,
+    html_url: String,
+    head: PullHead,
+}
+
+#[derive(Deserialize)]
+struct PullHead {
+    sha: String,
+}
+
+/// The statuses set through the commit status API.
+#[derive(Deserialize)]
+struct CombinedStatus {
+    state: String,
+    total_count: usize,
+}
+
+/// The checks reported through the checks API, e.g. by GitHub Actions.
+#[derive(Deserialize)]
+struct CheckRuns {
+    check_runs: Vec<CheckRun>,
+}
+
+#[derive(Deserialize)]
+struct CheckRun {
+    status: String,
+    conclusion: Option<String>,
+}
+
+pub struct GitHub {
+    project: Project,
+    /// Root of the REST API, which is on its own host for github.com.
+    api: String,
+    token: Option<String>,
+}
+
+impl GitHub {
+    pub fn new(project: Project) -> Self {
+        let api = if project.base == "https://github.com" {
+            "https://api.github.com".into()
+        } else {
+            // GitHub Enterprise Server
+            format!("{}/api/v3", project.base)
+        };
+        let host = super::host(&project);
+        // same order as gh itself: the environment, then what `gh auth login` stored
+        let token = super::env_token(&["GH_TOKEN", "GITHUB_TOKEN"])
+            .or_else(|| super::cli_token("gh", &["auth", "token", "--hostname", host]))
+            .or_else(|| {
+                super::config_file_token(
+                    super::cli_config_dir("GH_CONFIG_DIR", "gh").map(|d| d.join("hosts.yml")),
+                    host,
+                    "oauth_token",
+                )
+            });
+        GitHub {
+            project,
+            api,
+            token,
+        }
+    }
+}
+
+impl GitHub {
+    fn get<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let auth = self.token.as_ref().map(|t| format!("Bearer {}", t));
+        let mut headers = vec![("Accept", "application/vnd.github+json")];
+        if let Some(ref auth) = auth {
+            headers.push(("Authorization", auth));
+        }
+        super::parse("GitHub", &http::get(url, &headers)?)
+    }
+}
+
+impl Forge for GitHub {
+    fn pull_requests(&self, head: &Head) -> Result<Vec<PullRequest>> {
+        // branches are identified by the owner of the fork they're pushed to
+        let owner = head.project.path.split('/').next().unwrap_or_default();
+        let url = format!(
+            "{}/repos/{}/pulls?state=all&head={}:{}",
+            self.api,
+            self.project.path,
+            super::encode(owner, b""),
+            super::encode(&head.branch, b"")
+        );
+        let pulls: Vec<Pull> = self.get(&url)?;
+        Ok(pulls
+            .into_iter()
+            .map(|p| PullRequest {
+                number: p.number,
+                state: match p.state.as_str() {
+                    _ if p.merged_at.is_some() => State::Merged,
+                    "open" if p.draft => State::Draft,
+                    "open" => State::Open,
+                    _ => State::Closed,
+                },
+                url: p.html_url,
+                head: git2::Oid::from_str(&p.head.sha).ok(),
+            })
+            .collect())
+    }
+
+    fn ci_status(&self, head: &Head) -> Result<Option<CiStatus>> {
+        let commit = format!(
+            "{}/repos/{}/commits/{}",
+            self.api, self.project.path, head.tip
+        );
+        let status: CombinedStatus = self.get(&format!("{}/status", commit))?;
+        let checks: CheckRuns = self.get(&format!("{}/check-runs", commit))?;
+
+        // with no statuses at all, the combined state is "pending", which would be misleading
+        let status = match (status.total_count, status.state.as_str()) {
+            (0, _) => None,
+            (_, "success") => Some(CiStatus::Success),
+            (_, "pending") => Some(CiStatus::Pending),
+            _ => Some(CiStatus::Failure),
+        };
+        let checks = checks.check_runs.into_iter().map(|run| {
+            match (run.status.as_str(), run.conclusion.as_deref()) {
+                ("completed", Some("success" | "neutral" | "skipped")) => CiStatus::Success,
+                ("completed", _) => CiStatus::Failure,
+                _ => CiStatus::Pending,
+            }
+        });
+        Ok(CiStatus::combine(status.into_iter().chain(checks)))
+    }
+}
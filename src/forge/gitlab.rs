@@ -0,0 +1,104 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use super::{CiStatus, Forge, Head, Project, PullRequest, State};
+use crate::error::Result;
+use crate::http;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    iid: u64,
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    web_url: String,
+    sha: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    last_pipeline: Option<Pipeline>,
+}
+
+#[derive(Deserialize)]
+struct Pipeline {
+    status: String,
+}
+
+pub struct GitLab {
+    project: Project,
+    token: Option<String>,
+}
+
+impl GitLab {
+    pub fn new(project: Project) -> Self {
+        let host = super::host(&project);
+        let token = super::env_token(&["GITLAB_TOKEN"])
+            .or_else(|| super::cli_token("glab", &["config", "get", "token", "--host", host]))
+            .or_else(|| {
+                super::config_file_token(
+                    super::cli_config_dir("GLAB_CONFIG_DIR", "glab-cli")
+                        .map(|d| d.join("config.yml")),
+                    host,
+                    "token",
+                )
+            });
+        GitLab { project, token }
+    }
+}
+
+impl GitLab {
+    /// GET `path` under the project's API.
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        // projects can be looked up by their URL-encoded path instead of their ID
+        let url = format!(
+            "{}/api/v4/projects/{}/{}",
+            self.project.base,
+            super::encode(&self.project.path, b""),
+            path
+        );
+        let mut headers = Vec::new();
+        if let Some(ref token) = self.token {
+            headers.push(("PRIVATE-TOKEN", token.as_str()));
+        }
+        super::parse("GitLab", &http::get(&url, &headers)?)
+    }
+}
+
+impl Forge for GitLab {
+    fn pull_requests(&self, head: &Head) -> Result<Vec<PullRequest>> {
+        let mrs: Vec<MergeRequest> = self.get(&format!(
+            "merge_requests?state=all&source_branch={}",
+            super::encode(&head.branch, b"")
+        ))?;
+        Ok(mrs
+            .into_iter()
+            .map(|mr| PullRequest {
+                number: mr.iid,
+                state: match mr.state.as_str() {
+                    "merged" => State::Merged,
+                    "opened" if mr.draft => State::Draft,
+                    "opened" => State::Open,
+                    _ => State::Closed,
+                },
+                url: mr.web_url,
+                head: mr.sha.and_then(|sha| git2::Oid::from_str(&sha).ok()),
+            })
+            .collect())
+    }
+
+    fn ci_status(&self, head: &Head) -> Result<Option<CiStatus>> {
+        let commit: Commit = self.get(&format!("repository/commits/{}", head.tip))?;
+        Ok(commit
+            .last_pipeline
+            .and_then(|pipeline| match pipeline.status.as_str() {
+                "success" => Some(CiStatus::Success),
+                "failed" => Some(CiStatus::Failure),
+                // a pipeline which never ran says nothing either way
+                "canceled" | "skipped" => None,
+                _ => Some(CiStatus::Pending),
+            }))
+    }
+}
@@ -0,0 +1,511 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! The forges hosting remotes: linking branches to their page there for `open` and `show --web`,
+//! and looking up pull requests through the `Forge` trait for `--prs`.
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::utils;
+use log::debug;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+mod cache;
+mod gitea;
+mod github;
+mod gitlab;
+
+/// How long to trust cached answers from forges for, in seconds.
+const CACHE_TTL: u64 = 10 * 60;
+
+/// Which software a forge runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    GitHub,
+    GitLab,
+    Gitea,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Open,
+    Draft,
+    Merged,
+    Closed,
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            State::Open => "open",
+            State::Draft => "draft",
+            State::Merged => "merged",
+            State::Closed => "closed",
+        }
+    }
+}
+
+/// The combined status of the CI checks on a commit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CiStatus {
+    Success,
+    Failure,
+    Pending,
+}
+
+impl CiStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CiStatus::Success => "success",
+            CiStatus::Failure => "failure",
+            CiStatus::Pending => "pending",
+        }
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            CiStatus::Success => '✓',
+            CiStatus::Failure => '✗',
+            CiStatus::Pending => '●',
+        }
+    }
+
+    /// Combine the statuses of several checks: any failure fails the lot, and otherwise anything
+    /// still running makes it pending.
+    fn combine(statuses: impl IntoIterator<Item = CiStatus>) -> Option<CiStatus> {
+        statuses.into_iter().fold(None, |acc, s| match (acc, s) {
+            (Some(CiStatus::Failure), _) | (_, CiStatus::Failure) => Some(CiStatus::Failure),
+            (Some(CiStatus::Pending), _) | (_, CiStatus::Pending) => Some(CiStatus::Pending),
+            _ => Some(CiStatus::Success),
+        })
+    }
+}
+
+/// A pull request, or merge request in GitLab parlance.
+#[derive(Debug, Clone)]
+pub struct PullRequest {
+    pub number: u64,
+    pub state: State,
+    pub url: String,
+    /// The commit it was last updated to, if the forge says.
+    pub head: Option<git2::Oid>,
+}
+
+/// Where a branch lives on the forge: the project it's pushed to and its name there.
+pub struct Head {
+    pub project: Project,
+    pub branch: String,
+    /// The local branch's tip, which CI statuses are looked up for and cached answers are keyed
+    /// on.
+    pub tip: git2::Oid,
+}
+
+pub trait Forge {
+    /// List the pull requests opened from `head`, newest first.
+    fn pull_requests(&self, head: &Head) -> Result<Vec<PullRequest>>;
+
+    /// Get the combined status of the CI checks on `head`'s tip, if it has any.
+    fn ci_status(&self, head: &Head) -> Result<Option<CiStatus>>;
+}
+
+/// Where a remote lives: the web root of the forge (e.g. `https://github.com`) and the project
+/// path on it (e.g. `jlebon/git-bstatus`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub base: String,
+    pub path: String,
+}
+
+/// Split a remote URL into the forge's web root and the project. Handles `https://`, `ssh://` and scp-like
+/// `git@host:path` URLs; only http(s) ones keep their port, since for SSH it's not the web one.
+pub fn parse_url(url: &str) -> Option<Project> {
+    let (scheme, authority, path) = match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let (authority, path) = rest.split_once('/')?;
+            (scheme, authority, path)
+        }
+        // scp-like syntax; a colon before any slash tells it apart from a local path
+        None => match url.split_once(':') {
+            Some((authority, path)) if !authority.contains('/') => ("ssh", authority, path),
+            _ => return None,
+        },
+    };
+    if !matches!(
+        scheme,
+        "http" | "https" | "ssh" | "git" | "git+ssh" | "ssh+git"
+    ) {
+        return None;
+    }
+    let host = authority.rsplit('@').next()?;
+    let host = match scheme {
+        "http" | "https" => host,
+        _ => host.split(':').next()?,
+    };
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if host.is_empty() || path.is_empty() {
+        return None;
+    }
+    Some(Project {
+        base: format!(
+            "{}://{}",
+            if scheme == "http" { "http" } else { "https" },
+            host
+        ),
+        path: path.into(),
+    })
+}
+
+/// Guess which forge software serves `base`, for the well-known hosts and ones that say it in
+/// their name.
+fn guess_kind(base: &str) -> Option<Kind> {
+    let host = base.split("://").nth(1).unwrap_or(base).to_lowercase();
+    if host.contains("github") {
+        Some(Kind::GitHub)
+    } else if host.contains("gitlab") {
+        Some(Kind::GitLab)
+    } else if ["gitea", "forgejo", "codeberg"]
+        .iter()
+        .any(|name| host.contains(name))
+    {
+        Some(Kind::Gitea)
+    } else {
+        None
+    }
+}
+
+/// Percent-encode `s` for use in a URL, keeping any of `keep` as they are.
+fn encode(s: &str, keep: &[u8]) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) || keep.contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Get the URL of `branch` on `project`.
+pub fn branch_url(kind: Kind, project: &Project, branch: &str) -> String {
+    let (base, path, branch) = (&project.base, &project.path, encode(branch, b"/"));
+    match kind {
+        Kind::GitHub => format!("{}/{}/tree/{}", base, path, branch),
+        Kind::GitLab => format!("{}/{}/-/tree/{}", base, path, branch),
+        Kind::Gitea => format!("{}/{}/src/branch/{}", base, path, branch),
+    }
+}
+
+/// Find the remote and the name `name` has there: its upstream if it tracks a remote branch, and
+/// otherwise the branch of the same name on origin.
+fn remote_branch(repo: &git2::Repository, name: &str) -> Result<(String, String)> {
+    let branch = match repo.find_branch(name, git2::BranchType::Local) {
+        Ok(branch) => branch,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            return Err(Error::NotFound(format!("branch {} not found", name)))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let config = repo.config()?;
+    let remote = config.get_string(&format!("branch.{}.remote", name));
+    let merge = config.get_string(&format!("branch.{}.merge", name));
+    if let (Ok(remote), Ok(merge), true) = (remote, merge, branch.upstream().is_ok()) {
+        if remote != "." {
+            let merge = merge
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&merge)
+                .to_string();
+            return Ok((remote, merge));
+        }
+    }
+    Ok(("origin".into(), name.into()))
+}
+
+/// Get the project remote `name` points to.
+fn remote_project(repo: &git2::Repository, name: &str) -> Result<Project> {
+    let remote = match repo.find_remote(name) {
+        Ok(remote) => remote,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            return Err(Error::NotFound(format!("remote {} not found", name)))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let url = remote.url().unwrap_or_default();
+    parse_url(url).ok_or_else(|| Error::NotFound(format!("can't tell where {} is on the web", url)))
+}
+
+/// Find out which forge serves `project`: from `bstatus.forge`, or guessed from the host name.
+fn kind(repo: &git2::Repository, project: &Project) -> Result<Kind> {
+    match repo.config()?.get_string(config::FORGE) {
+        Ok(kind) => match kind.to_lowercase().as_str() {
+            "github" => Ok(Kind::GitHub),
+            "gitlab" => Ok(Kind::GitLab),
+            "gitea" | "forgejo" => Ok(Kind::Gitea),
+            _ => Err(Error::BadConfig {
+                key: config::FORGE.into(),
+                value: kind,
+                reason: "forges are github, gitlab and gitea".into(),
+            }),
+        },
+        Err(e) if e.code() == git2::ErrorCode::NotFound => match guess_kind(&project.base) {
+            Some(kind) => Ok(kind),
+            None => Err(Error::NotFound(format!(
+                "don't know which forge runs {} (set bstatus.forge?)",
+                project.base
+            ))),
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get the URL of local branch `name` on the forge hosting its remote.
+pub fn url(repo: &git2::Repository, name: &str) -> Result<String> {
+    let (remote, branch) = remote_branch(repo, name)?;
+    let project = remote_project(repo, &remote)?;
+    Ok(branch_url(kind(repo, &project)?, &project, &branch))
+}
+
+/// Find where local branch `name` is on the forge, or `None` if its remote isn't on one.
+pub fn head(repo: &git2::Repository, name: &str, tip: git2::Oid) -> Result<Option<Head>> {
+    let (remote, branch) = remote_branch(repo, name)?;
+    match remote_project(repo, &remote) {
+        Ok(project) => Ok(Some(Head {
+            project,
+            branch,
+            tip,
+        })),
+        Err(Error::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the forge hosting origin, to look up pull requests on. Its answers are cached for
+/// `bstatus.forge-cache-ttl` (10 minutes by default; 0 turns caching off).
+pub fn open(repo: &git2::Repository) -> Result<Box<dyn Forge>> {
+    let project = remote_project(repo, "origin")?;
+    let forge: Box<dyn Forge> = match kind(repo, &project)? {
+        Kind::GitHub => Box::new(github::GitHub::new(project)),
+        Kind::GitLab => Box::new(gitlab::GitLab::new(project)),
+        Kind::Gitea => {
+            let token = match env_token(&["GITEA_TOKEN", "FORGEJO_TOKEN"]) {
+                Some(token) => Some(token),
+                None => match repo.config()?.get_string(config::GITEA_TOKEN) {
+                    Ok(token) => Some(token),
+                    Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                },
+            };
+            Box::new(gitea::Gitea::new(project, token))
+        }
+    };
+
+    let ttl = match repo.config()?.get_string(config::FORGE_CACHE_TTL) {
+        Ok(ttl) if ttl == "0" => return Ok(forge),
+        Ok(ttl) => match utils::parse_duration(&ttl) {
+            Some(ttl) => ttl,
+            None => {
+                return Err(Error::BadConfig {
+                    key: config::FORGE_CACHE_TTL.into(),
+                    value: ttl,
+                    reason: "expected a duration like 10 minutes or 1h, or 0".into(),
+                })
+            }
+        },
+        Err(e) if e.code() == git2::ErrorCode::NotFound => CACHE_TTL,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Box::new(cache::Cached::new(forge, repo, ttl)))
+}
+
+/// Get a token from the first of the `vars` set in the environment.
+fn env_token(vars: &[&str]) -> Option<String> {
+    vars.iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|token| !token.is_empty())
+}
+
+/// Get the host name of `project`'s forge without the scheme or port, which is what the forges'
+/// CLIs key their logins on.
+fn host(project: &Project) -> &str {
+    let host = project.base.split("://").nth(1).unwrap_or(&project.base);
+    host.split(':').next().unwrap_or(host)
+}
+
+/// Ask a forge's CLI for the token it logged in with, e.g. `gh auth token`.
+fn cli_token(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    if token.is_empty() {
+        return None;
+    }
+    debug!("using token from {}", program);
+    Some(token)
+}
+
+/// Get the directory where a CLI keeps its config: `$<env>` if set, and otherwise `name` under
+/// the XDG config directory.
+fn cli_config_dir(env: &str, name: &str) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(env) {
+        return Some(dir.into());
+    }
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join(name))
+}
+
+/// Find `key` under `host` in a CLI's YAML config, e.g. the `oauth_token` in gh's `hosts.yml`:
+///
+/// ```yaml
+/// github.com:
+///     oauth_token: gho_xxx
+/// ```
+///
+/// Just enough YAML for that; anything fancier (e.g. a keyring) is left to the CLI itself.
+fn hosts_file_token(contents: &str, host: &str, key: &str) -> Option<String> {
+    let host_line = format!("{}:", host);
+    let mut host_indent = None;
+    for line in contents.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        match host_indent {
+            Some(h) if indent > h => {
+                let value = trimmed.strip_prefix(key).and_then(|v| v.strip_prefix(':'));
+                if let Some(value) = value.map(|v| v.trim().trim_matches(|c| c == '"' || c == '\''))
+                {
+                    if !value.is_empty() {
+                        return Some(value.to_string());
+                    }
+                }
+            }
+            _ => {
+                host_indent = if trimmed.trim_end() == host_line {
+                    Some(indent)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Read `key` for `host` from the YAML config file at `path`.
+fn config_file_token(path: Option<PathBuf>, host: &str, key: &str) -> Option<String> {
+    let path = path?;
+    let token = hosts_file_token(&std::fs::read_to_string(&path).ok()?, host, key)?;
+    debug!("using token from {}", path.display());
+    Some(token)
+}
+
+/// Parse a JSON response from `what`.
+fn parse<T: serde::de::DeserializeOwned>(what: &str, body: &[u8]) -> Result<T> {
+    serde_json::from_slice(body)
+        .map_err(|e| Error::Backend(format!("parsing {} response: {}", what, e).into()))
+}
+
+/// Open `url` in `$BROWSER`, or the desktop's default browser.
+pub fn browse(url: &str) -> Result<()> {
+    let mut cmd = match std::env::var_os("BROWSER") {
+        Some(browser) => std::process::Command::new(browser),
+        None if cfg!(target_os = "macos") => std::process::Command::new("open"),
+        None if cfg!(windows) => {
+            let mut cmd = std::process::Command::new("cmd");
+            cmd.args(["/c", "start", ""]);
+            cmd
+        }
+        None => std::process::Command::new("xdg-open"),
+    };
+    let status = cmd
+        .arg(url)
+        .status()
+        .map_err(|e| Error::Command(format!("opening {}: {}", url, e)))?;
+    if !status.success() {
+        return Err(Error::Command(format!("opening {}: {}", url, status)));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_url() {
+    let project = |base: &str, path: &str| {
+        Some(Project {
+            base: base.into(),
+            path: path.into(),
+        })
+    };
+    let gh = project("https://github.com", "jlebon/git-bstatus");
+    assert_eq!(gh, parse_url("https://github.com/jlebon/git-bstatus.git"));
+    assert_eq!(gh, parse_url("https://user@github.com/jlebon/git-bstatus/"));
+    assert_eq!(gh, parse_url("git@github.com:jlebon/git-bstatus.git"));
+    assert_eq!(gh, parse_url("ssh://git@github.com:22/jlebon/git-bstatus"));
+    assert_eq!(
+        project("http://git.example.com:3000", "a/b/c"),
+        parse_url("http://git.example.com:3000/a/b/c")
+    );
+    assert_eq!(None, parse_url("/srv/git/repo.git"));
+    assert_eq!(None, parse_url("../repo"));
+    assert_eq!(None, parse_url("file:///srv/git/repo.git"));
+}
+
+#[test]
+fn test_combine() {
+    use CiStatus::*;
+    assert_eq!(None, CiStatus::combine(vec![]));
+    assert_eq!(Some(Success), CiStatus::combine(vec![Success, Success]));
+    assert_eq!(Some(Pending), CiStatus::combine(vec![Success, Pending]));
+    assert_eq!(
+        Some(Failure),
+        CiStatus::combine(vec![Pending, Failure, Success])
+    );
+}
+
+#[test]
+fn test_hosts_file_token() {
+    let gh = "github.com:\n    user: me\n    oauth_token: gho_abc\nghe.example.com:\n    oauth_token: gho_def\n";
+    assert_eq!(
+        Some("gho_abc".into()),
+        hosts_file_token(gh, "github.com", "oauth_token")
+    );
+    assert_eq!(
+        Some("gho_def".into()),
+        hosts_file_token(gh, "ghe.example.com", "oauth_token")
+    );
+    assert_eq!(None, hosts_file_token(gh, "gitlab.com", "oauth_token"));
+    let glab = "git_protocol: ssh\nhosts:\n  gitlab.com:\n    token: \"glpat-xyz\"\n    api_host: gitlab.com\n";
+    assert_eq!(
+        Some("glpat-xyz".into()),
+        hosts_file_token(glab, "gitlab.com", "token")
+    );
+}
+
+#[test]
+fn test_branch_url() {
+    let project = parse_url("git@gitlab.com:group/sub/proj.git").unwrap();
+    assert_eq!(Some(Kind::GitLab), guess_kind(&project.base));
+    assert_eq!(
+        "https://gitlab.com/group/sub/proj/-/tree/feature/a%23b",
+        branch_url(Kind::GitLab, &project, "feature/a#b")
+    );
+    let project = parse_url("https://codeberg.org/me/proj").unwrap();
+    assert_eq!(Some(Kind::Gitea), guess_kind(&project.base));
+    assert_eq!(
+        "https://codeberg.org/me/proj/src/branch/main",
+        branch_url(Kind::Gitea, &project, "main")
+    );
+}
@@ -0,0 +1,129 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Looking up the Gerrit changes for the commits on a branch, for `--gerrit`.
+
+use crate::compare;
+use crate::config;
+use crate::error::{Error, Result};
+use crate::forge;
+use crate::http;
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize)]
+pub struct Change {
+    #[serde(rename = "_number")]
+    pub number: u64,
+    /// NEW, MERGED or ABANDONED.
+    pub status: String,
+    change_id: String,
+}
+
+pub struct Gerrit {
+    /// Root of the REST API, e.g. `https://review.example.com` (or `.../a` for authenticated
+    /// requests).
+    base: String,
+    project: Option<String>,
+}
+
+impl Gerrit {
+    /// Find the Gerrit server from `bstatus.gerrit-url`, or failing that, origin's URL.
+    pub fn new(repo: &git2::Repository) -> Result<Self> {
+        let origin = match repo.find_remote("origin") {
+            Ok(remote) => remote.url().and_then(forge::parse_url),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let base = match repo.config()?.get_string(config::GERRIT_URL) {
+            Ok(url) => url.trim_end_matches('/').to_string(),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => match origin {
+                Some(ref project) => project.base.clone(),
+                None => {
+                    return Err(Error::NotFound(
+                        "can't tell where Gerrit is (set bstatus.gerrit-url?)".into(),
+                    ))
+                }
+            },
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Gerrit {
+            base,
+            // repos can be cloned from the authenticated /a/ path too
+            project: origin.map(|p| p.path.strip_prefix("a/").unwrap_or(&p.path).to_string()),
+        })
+    }
+
+    /// Get the changes for the commits on `oid` which aren't on `base`, along with whether every
+    /// one of those commits has a change, i.e. whether the changes cover the whole branch.
+    pub fn changes(
+        &self,
+        repo: &git2::Repository,
+        oid: git2::Oid,
+        base: git2::Oid,
+        max_ahead: Option<usize>,
+    ) -> Result<(Vec<Change>, bool)> {
+        let (oids, capped) = compare::only_in(repo, oid, base, max_ahead)?;
+        let mut ids = Vec::new();
+        for oid in &oids {
+            let commit = repo.find_commit(*oid)?;
+            if let Some(id) = change_id(&String::from_utf8_lossy(commit.message_bytes())) {
+                ids.push(id);
+            }
+        }
+        if ids.is_empty() {
+            return Ok((Vec::new(), false));
+        }
+
+        let mut query = ids
+            .iter()
+            .map(|id| format!("change:{}", id))
+            .collect::<Vec<_>>()
+            .join("+OR+");
+        if let Some(ref project) = self.project {
+            query = format!("({})+project:{}", query, project.replace('/', "%2F"));
+        }
+        let body = http::get(&format!("{}/changes/?q={}", self.base, query), &[])?;
+        // Gerrit prefixes its JSON with this to stop it from being run as JavaScript
+        let body = body.strip_prefix(b")]}'").unwrap_or(&body);
+        let found: Vec<Change> = serde_json::from_slice(body)
+            .map_err(|e| Error::Backend(format!("parsing Gerrit response: {}", e).into()))?;
+
+        // keep them in branch order; a change can show up more than once if it was cherry-picked
+        // to other branches, in which case what happened to any of them will do
+        let mut changes: Vec<Change> = Vec::new();
+        for id in &ids {
+            if let Some(c) = found.iter().find(|c| &c.change_id == id) {
+                if !changes.iter().any(|seen| seen.number == c.number) {
+                    changes.push(c.clone());
+                }
+            }
+        }
+        let covered = !capped && ids.len() == oids.len() && changes.len() == ids.len();
+        Ok((changes, covered))
+    }
+}
+
+/// Get the `Change-Id` trailer of a commit message.
+fn change_id(message: &str) -> Option<String> {
+    let trailers = git2::message_trailers_strs(message).ok()?;
+    let id = trailers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("Change-Id"))
+        .map(|(_, value)| value.trim().to_string())
+        .next_back();
+    id
+}
+
+#[test]
+fn test_change_id() {
+    let id = "I8473b95934b5732ac55d26311a706c9c2bde9940";
+    assert_eq!(
+        Some(id.to_string()),
+        change_id(&format!("fix: thing\n\nBody.\n\nChange-Id: {}\n", id))
+    );
+    assert_eq!(
+        None,
+        change_id("fix: thing\n\nChange-Id is mentioned here.\n")
+    );
+}
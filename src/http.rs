@@ -0,0 +1,54 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Talking to web APIs, through curl so we get its proxy, TLS and `.netrc` handling for free.
+
+use crate::error::{Error, Result};
+use log::debug;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// GET `url` with the given extra headers, returning the body. Fails on HTTP errors. The headers
+/// go to curl on its stdin rather than its command line, where anyone could see tokens in them.
+pub fn get(url: &str, headers: &[(&str, &str)]) -> Result<Vec<u8>> {
+    let mut cmd = Command::new("curl");
+    cmd.args([
+        "--silent",
+        "--show-error",
+        "--fail",
+        "--location",
+        "--globoff",
+        "--netrc-optional",
+    ]);
+    if !headers.is_empty() {
+        cmd.args(["--header", "@-"]);
+    }
+    debug!("GET {}", url);
+    let mut child = cmd
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Command(format!("running curl: {}", e)))?;
+    // closed once written, so curl sees the end of them; if curl gave up before reading them,
+    // its own error says more than the broken pipe
+    let mut stdin = child.stdin.take().unwrap();
+    let written = headers
+        .iter()
+        .try_for_each(|(name, value)| writeln!(stdin, "{}: {}", name, value));
+    drop(stdin);
+    let output = child
+        .wait_with_output()
+        .map_err(|e| Error::Command(format!("running curl: {}", e)))?;
+    if !output.status.success() {
+        return Err(Error::Command(format!(
+            "GET {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    written?;
+    Ok(output.stdout)
+}
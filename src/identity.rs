@@ -0,0 +1,99 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Matching commits against people, with identities resolved through `.mailmap` so that someone
+//! who changed names or emails is still recognized.
+
+use crate::error::{Error, Result};
+
+pub struct Identities {
+    mailmap: git2::Mailmap,
+}
+
+impl Identities {
+    pub fn new(repo: &git2::Repository) -> Result<Self> {
+        Ok(Identities {
+            mailmap: repo.mailmap()?,
+        })
+    }
+
+    /// Get the (mailmapped) author and committer of `commit`.
+    pub fn of(&self, commit: &git2::Commit) -> Result<[git2::Signature<'static>; 2]> {
+        Ok([
+            commit.author_with_mailmap(&self.mailmap)?,
+            commit.committer_with_mailmap(&self.mailmap)?,
+        ])
+    }
+
+    /// Get the (mailmapped) email of the configured `user.email`.
+    pub fn me(&self, repo: &git2::Repository) -> Result<String> {
+        let config = repo.config()?;
+        let email = match config.get_string("user.email") {
+            Ok(email) => email,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                return Err(Error::NotFound("user.email is not configured".into()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // the name only matters for mailmap entries which match on both
+        let name = config
+            .get_string("user.name")
+            .unwrap_or_else(|_| email.clone());
+        let sig = self
+            .mailmap
+            .resolve_signature(&git2::Signature::now(&name, &email)?)?;
+        Ok(String::from_utf8_lossy(sig.email_bytes()).into())
+    }
+
+    /// Check whether `commit` was authored or committed by someone for whom `pred` is true.
+    pub fn matches<F>(&self, commit: &git2::Commit, pred: F) -> Result<bool>
+    where
+        F: Fn(&git2::Signature) -> bool,
+    {
+        Ok(self.of(commit)?.iter().any(pred))
+    }
+
+    /// Like `matches`, but checks all the commits reachable from `oid` and not from `base`,
+    /// stopping after `max` commits.
+    pub fn matches_any<F>(
+        &self,
+        repo: &git2::Repository,
+        oid: git2::Oid,
+        base: git2::Oid,
+        max: Option<usize>,
+        pred: F,
+    ) -> Result<bool>
+    where
+        F: Fn(&git2::Signature) -> bool,
+    {
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(oid)?;
+        revwalk.hide(base)?;
+        for maybe_oid in revwalk.take(max.unwrap_or(usize::MAX)) {
+            let oid = match maybe_oid {
+                Ok(oid) => oid,
+                // in shallow clones, the walk fails once it reaches the cut-off; whatever we've
+                // seen until then is all there is to go on
+                Err(e) if e.code() == git2::ErrorCode::NotFound && repo.is_shallow() => break,
+                Err(e) => return Err(e.into()),
+            };
+            if self.matches(&repo.find_commit(oid)?, &pred)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Check whether the name or email in `sig` contains `pattern`.
+pub fn contains(sig: &git2::Signature, pattern: &str) -> bool {
+    let name = String::from_utf8_lossy(sig.name_bytes());
+    let email = String::from_utf8_lossy(sig.email_bytes());
+    name.contains(pattern) || email.contains(pattern)
+}
+
+/// Check whether the email in `sig` is `email`. Like git, the comparison is case-insensitive.
+pub fn has_email(sig: &git2::Signature, email: &str) -> bool {
+    String::from_utf8_lossy(sig.email_bytes()).eq_ignore_ascii_case(email)
+}
@@ -0,0 +1,280 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Machine-readable output. A single repository is printed as a `Repo` object; scanning several
+//! (with `--recursive` or `--recurse-submodules`) prints them all nested in an `Aggregate`. Either
+//! way, it's described by `schema.json`, which `--schema` prints.
+
+use crate::error::{Error, Result};
+use crate::{BranchInfo, BranchesInfo};
+use serde::Serialize;
+use std::path::Path;
+
+/// Bumped whenever the output changes in a way which could break consumers, i.e. anything but
+/// adding fields. Keep `schema.json` in sync.
+const FORMAT_VERSION: u32 = 1;
+
+pub const SCHEMA: &str = include_str!("schema.json");
+
+/// What actually gets printed: the output, tagged with its format version.
+#[derive(Serialize)]
+struct Versioned<'a, T: Serialize> {
+    format_version: u32,
+    #[serde(flatten)]
+    value: &'a T,
+}
+
+#[derive(Serialize)]
+pub struct Aggregate {
+    pub repositories: Vec<Repo>,
+    pub totals: Totals,
+}
+
+#[derive(Serialize)]
+pub struct Totals {
+    pub repositories: usize,
+    pub branches: usize,
+    pub merged: usize,
+    pub unmerged: usize,
+}
+
+#[derive(Serialize)]
+pub struct Repo {
+    pub path: String,
+    /// Set instead of everything else if the repository couldn't be scanned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    pub merged: usize,
+    pub unmerged: usize,
+    pub branches: Vec<Branch>,
+    /// The commit checked out, if HEAD is detached; it's not a branch, so it's kept apart.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detached: Option<Branch>,
+}
+
+#[derive(Serialize)]
+pub struct Branch {
+    pub name: String,
+    pub active: bool,
+    pub oid: String,
+    pub timestamp: u64,
+    pub summary: String,
+    pub ahead: usize,
+    pub ahead_capped: bool,
+    pub ahead_lower_bound: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merges: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<Upstream>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_remote: Option<bool>,
+    /// When the branch was last checked out, with `--sort=checkout` or `--visited`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_checkout: Option<i64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dirty: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gerrit: Option<Vec<Change>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<PullRequest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ci: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issues: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailers: Option<Vec<Trailer>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub types: Option<Vec<CommitType>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bump: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<Size>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Change {
+    pub number: u64,
+    pub status: String,
+}
+
+#[derive(Serialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub state: &'static str,
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct Trailer {
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct CommitType {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct Size {
+    pub objects: usize,
+    pub bytes: u64,
+    pub capped: bool,
+}
+
+#[derive(Serialize)]
+pub struct Upstream {
+    pub name: String,
+    pub push: usize,
+    pub pull: usize,
+}
+
+fn display_path(path: &Path) -> String {
+    // drop the trailing slash libgit2 leaves on workdirs
+    path.components().as_path().display().to_string()
+}
+
+fn branch(b: &BranchInfo) -> Branch {
+    Branch {
+        name: b.name.clone(),
+        active: b.active,
+        oid: b.oid.to_string(),
+        timestamp: b.timestamp,
+        summary: b.summary.clone(),
+        ahead: b.ahead,
+        ahead_capped: b.ahead_capped,
+        ahead_lower_bound: b.ahead_lower_bound,
+        total: b.total,
+        merges: b.merges,
+        upstream: b.upstream.as_ref().map(|name| Upstream {
+            name: name.clone(),
+            push: b.push_pull.0,
+            pull: b.push_pull.1,
+        }),
+        worktree: b.worktree.as_deref().map(display_path),
+        on_remote: b.on_remote,
+        last_checkout: b.last_checkout,
+        dirty: b.touches_dirty,
+        parent: b.parent.clone(),
+        base: b.base.clone(),
+        release: b.release.clone(),
+        note: b.note.clone(),
+        gerrit: b.changes.as_ref().map(|changes| {
+            changes
+                .iter()
+                .map(|c| Change {
+                    number: c.number,
+                    status: c.status.clone(),
+                })
+                .collect()
+        }),
+        pull_request: b.pull_request.as_ref().map(|pr| PullRequest {
+            number: pr.number,
+            state: pr.state.as_str(),
+            url: pr.url.clone(),
+        }),
+        ci: b.ci.map(|ci| ci.as_str()),
+        issues: b.issues.clone(),
+        bump: b.bump.map(|bump| bump.as_str()),
+        size: b.size.map(|size| Size {
+            objects: size.objects,
+            bytes: size.bytes,
+            capped: size.capped,
+        }),
+        types: b.types.as_ref().map(|types| {
+            types
+                .iter()
+                .map(|(kind, count)| CommitType {
+                    kind: kind.clone(),
+                    count: *count,
+                })
+                .collect()
+        }),
+        trailers: b.trailers.as_ref().map(|trailers| {
+            trailers
+                .iter()
+                .map(|(key, values)| Trailer {
+                    key: key.clone(),
+                    values: values.clone(),
+                })
+                .collect()
+        }),
+        signature: b.signature.map(|c| c.to_string()),
+    }
+}
+
+/// Get the path by which to identify `repo`: its working tree, or its git dir if it's bare.
+pub fn repo_path(repo: &git2::Repository) -> String {
+    display_path(repo.workdir().unwrap_or_else(|| repo.path()))
+}
+
+pub fn repo(path: String, info: &BranchesInfo) -> Repo {
+    Repo {
+        path,
+        error: None,
+        default_branch: info.default_branch.clone(),
+        merged: info.n_merged,
+        unmerged: info.n_unmerged,
+        branches: info
+            .branches
+            .iter()
+            .filter(|b| !b.detached)
+            .map(branch)
+            .collect(),
+        detached: info.branches.iter().find(|b| b.detached).map(branch),
+    }
+}
+
+pub fn repo_error(path: String, e: &Error) -> Repo {
+    Repo {
+        path,
+        error: Some(e.to_string()),
+        default_branch: None,
+        merged: 0,
+        unmerged: 0,
+        branches: Vec::new(),
+        detached: None,
+    }
+}
+
+pub fn aggregate(repositories: Vec<Repo>) -> Aggregate {
+    let totals = Totals {
+        repositories: repositories.len(),
+        branches: repositories.iter().map(|r| r.branches.len()).sum(),
+        merged: repositories.iter().map(|r| r.merged).sum(),
+        unmerged: repositories.iter().map(|r| r.unmerged).sum(),
+    };
+    Aggregate {
+        repositories,
+        totals,
+    }
+}
+
+pub fn print<T: Serialize>(value: &T) -> Result<()> {
+    let versioned = Versioned {
+        format_version: FORMAT_VERSION,
+        value,
+    };
+    let s = serde_json::to_string_pretty(&versioned).map_err(|e| Error::Io(e.into()))?;
+    println!("{}", s);
+    Ok(())
+}
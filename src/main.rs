@@ -3,10 +3,41 @@
  * */
 
 use ansi_term::{Colour, Style};
-use clap::clap_app;
-use std::error::Error;
+use clap::{clap_app, value_t};
+use error::{Context, Error, Result};
+use log::debug;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+mod backend;
+mod backup;
+mod bases;
+mod commits;
+mod compare;
+mod config;
+mod describe;
+mod error;
+mod forge;
+mod gerrit;
+mod http;
+mod identity;
+mod json;
+mod metrics;
+mod pager;
+mod pattern;
+mod placeholders;
+mod profile;
+mod remote;
+mod remote_only;
+mod restack;
+mod setup;
+mod show;
+mod signature;
+mod sizes;
+mod switch;
 mod utils;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -15,6 +46,10 @@ enum OutputMode {
     Listing,
     ListingCommits,
     NameOnly,
+    Eval,
+    RemotesMatrix,
+    BasesMatrix,
+    Json,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -25,19 +60,212 @@ enum BranchFilter {
     Unmerged,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Sort {
+    /// By the time of the last commit.
+    Date,
+    /// By when branches were last checked out.
+    Checkout,
+}
+
+/// The parts of the default output after the current branch, see `bstatus.human-sections`.
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Recent,
+    Stale,
+    Stash,
+    Counts,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    Flat,
+    Tree,
+    Stacks,
+}
+
 struct BranchInfo {
+    /// The name as is, which may not be valid UTF-8.
+    raw_name: Vec<u8>,
+    /// The name with anything unprintable escaped.
     name: String,
     active: bool,
+    /// Whether this is the row for a detached HEAD rather than a branch. It's only there to be
+    /// looked at, so it doesn't count towards matches, `--fail-if-*` or metrics.
+    detached: bool,
     timestamp: u64,
     timestamp_rel: String,
     summary: String,
     ahead: usize,
+    ahead_capped: bool,
+    ahead_lower_bound: bool,
+    /// Number of commits reachable from the tip at all, or since `--total=REV`. Only filled in
+    /// with `--total`.
+    total: Option<usize>,
+    /// How many of the commits ahead are merges. Only filled in with `--merges`.
+    merges: Option<usize>,
     oid: git2::Oid,
     upstream: Option<String>,
+    /// Number of commits to push to and pull from the upstream branch.
+    push_pull: (usize, usize),
+    /// Number of commits to push to and pull from the branch of the same name on each remote in
+    /// `BranchesInfo::remotes`, if it exists there. Only filled in with `--vs-remotes`.
+    vs_remotes: Vec<Option<(usize, usize)>>,
+    /// Number of commits ahead of each of `BranchesInfo::bases`, if they share any history. Only
+    /// filled in with `--bases`.
+    vs_bases: Vec<Option<usize>>,
+    /// Number of commits ahead of and behind the default branch. Only filled in with
+    /// `--vs-default`.
+    vs_default: Option<(usize, usize)>,
+    /// How long ago the branch forked off its base. Only filled in with `--branched`.
+    branched: Option<String>,
+    /// For merged branches listed with `-m`, what they were merged into and how long ago.
+    merged_into: Option<(Option<String>, String)>,
+    /// Result of checking signatures with `--show-signature`, see `signature::verify()`.
+    signature: Option<char>,
+    /// For the detached HEAD row, the nearest branch containing it.
+    contained_in: Option<String>,
+    /// Whether the tip is reachable from any remote-tracking branch, i.e. is backed up somewhere.
+    /// Only filled in with `--pushed`.
+    on_remote: Option<bool>,
+    /// The worktree the branch is checked out in, if any.
+    worktree: Option<PathBuf>,
+    /// When the branch was last checked out according to the HEAD reflog, if it ever was. Only
+    /// filled in with `--sort=checkout` or `--visited`.
+    last_checkout: Option<i64>,
+    /// How well the name matched the patterns with `--fuzzy`, higher being better.
+    match_score: i64,
+    touches_dirty: bool,
+    /// Whether the branch counts as merged, as for `-m` and `-u`.
+    merged: bool,
+    parent: Option<String>,
+    /// The branch from `bstatus.bases` the branch was compared against, if it was.
+    base: Option<String>,
+    release: Option<String>,
+    /// First line of the note attached to the tip, with `--notes`.
+    note: Option<String>,
+    /// Gerrit changes for the commits on the branch, with `--gerrit`.
+    changes: Option<Vec<gerrit::Change>>,
+    /// The latest pull request opened from the branch, with `--prs`.
+    pull_request: Option<forge::PullRequest>,
+    /// Combined status of the CI checks on the tip, with `--ci`.
+    ci: Option<forge::CiStatus>,
+    /// Issues referenced by the commits on the branch, with `--issues`.
+    issues: Option<Vec<String>>,
+    /// Values of the trailers on the commits on the branch, by key, with `--trailers`.
+    trailers: Option<Vec<(String, Vec<String>)>>,
+    /// How many commits of each conventional-commit type are on the branch, with `--types`.
+    types: Option<Vec<(String, usize)>>,
+    /// The version bump the commits on the branch call for, with `--bump`.
+    bump: Option<commits::Bump>,
+    /// What the commits on the branch add to the repository, with `--sizes`.
+    size: Option<sizes::Size>,
+}
+
+impl BranchInfo {
+    /// Like `git branch`, `*` marks the current branch and `+` those checked out in other
+    /// worktrees.
+    fn marker(&self) -> &'static str {
+        if self.active {
+            "*"
+        } else if self.worktree.is_some() {
+            "+"
+        } else {
+            " "
+        }
+    }
+}
+
+/// Settings derived from the command line.
+struct Options<'a> {
+    patterns: Option<Vec<&'a str>>,
+    ignore_case: bool,
+    fuzzy: bool,
+    /// What to match the patterns against: any of "name", "upstream" and "refname".
+    match_on: Vec<&'a str>,
+    output_mode: OutputMode,
+    /// Branches (or globs) to compare every branch against with `--bases`.
+    bases: Vec<&'a str>,
+    filter: BranchFilter,
+    /// What to check whether branches are merged into with `-m REF` or `-u REF`, instead of their
+    /// base. If there's no such ref, it's a pattern after a bare `-m` or `-u`.
+    merged_ref: Option<&'a str>,
+    reverse: bool,
+    sort: Sort,
+    /// Go by author rather than commit dates, see `commit_time()`.
+    author_date: bool,
+    mark_dirty: bool,
+    show_parent: bool,
+    show_release: bool,
+    show_notes: bool,
+    gerrit: bool,
+    prs: bool,
+    ci: bool,
+    show_issues: bool,
+    show_trailers: bool,
+    show_types: bool,
+    show_bump: bool,
+    show_sizes: bool,
+    show_merges: bool,
+    base_tag: Option<&'a str>,
+    /// With `--total`, what to count commits since, if not the root.
+    total: Option<Option<&'a str>>,
+    first_parent: bool,
+    max_ahead: Option<usize>,
+    /// Only list branches with at least this many commits ahead.
+    min_ahead: Option<usize>,
+    /// Only list branches with at most this many commits ahead.
+    max_ahead_filter: Option<usize>,
+    backend: &'a str,
+    author: Option<&'a str>,
+    /// With `-v`, only list the commits authored or committed by this.
+    commit_author: Option<&'a str>,
+    /// Print the diff of each commit listed.
+    patch: bool,
+    /// Sum up the branches listed at the end.
+    stat_footer: bool,
+    /// With `-a`, list unmerged and merged branches separately.
+    sections: bool,
+    /// With `-n`, lay the names out in columns.
+    column: bool,
+    /// With `-n`, end each name with a NUL rather than a newline.
+    null: bool,
+    grep: Option<&'a str>,
+    mine: bool,
+    remote: Option<&'a str>,
+    unpushed: bool,
+    show_pushed: bool,
+    resolve_head: bool,
+    fork_point: bool,
+    vs_default: bool,
+    show_branched: bool,
+    show_visited: bool,
+    show_signature: bool,
+    verify_all: bool,
+    show_changes: bool,
+    show_worktrees: bool,
+    recurse_submodules: bool,
+    layout: Layout,
+    /// No colours or alignment, e.g. because we're piped into something.
+    plain: bool,
+    profile: profile::Profile,
+    fail_if_match: bool,
+    /// Maximum age in seconds of the last commit on branches listed, for `--fail-if-older-than`.
+    fail_if_older_than: Option<u64>,
+    /// How many branches were listed across all the repositories scanned.
+    matched: Cell<usize>,
+    /// How many of those were older than `--fail-if-older-than`.
+    stale: Cell<usize>,
+    metrics: Option<metrics::Metrics>,
 }
 
 struct BranchesInfo {
     branches: Vec<BranchInfo>,
+    /// Short name of the default branch, if it is one.
+    default_branch: Option<String>,
+    remotes: Vec<String>,
+    /// The branches given with `--bases`.
+    bases: Vec<String>,
     n_merged: usize,
     n_unmerged: usize,
 }
@@ -47,26 +275,384 @@ const RECENT_N: usize = 5;
 const LOCAL_BRANCH_REF_PREFIX: &str = "refs/heads/";
 
 fn main() {
-    let matches = clap::clap_app!((clap::crate_name!()) =>
+    // the Windows console only understands ANSI colours once asked to; on consoles too old for
+    // that, there's not much we can do
+    #[cfg(windows)]
+    let _ = ansi_term::enable_ansi_support();
+
+    let app = clap::clap_app!((clap::crate_name!()) =>
             (version: clap::crate_version!())
             (author: clap::crate_authors!())
             (about: clap::crate_description!())
             (@arg REPO: --repo +takes_value "Git repo to target")
+            (@arg git_dir: --("git-dir") +takes_value value_name("PATH") conflicts_with[REPO]
+                "Path to the repository's git directory, like git --git-dir")
+            (@arg recursive: --recursive +takes_value value_name("DIR")
+                conflicts_with[REPO git_dir] "Scan every repository found under DIR")
             (@arg BRANCH: ... "Branches to list (or substrings)")
+            (@arg ignore_case: -i --("ignore-case")
+                "Match patterns regardless of case (the default if they're all lowercase)")
+            (@arg fuzzy: --fuzzy
+                "Match patterns as subsequences, e.g. 'usrlgn' for 'user-login', best first")
+            (@arg match_on: --("match-on") +takes_value +use_delimiter
+                possible_values(&["name", "upstream", "refname"]) default_value("name")
+                value_name("WHAT") "Match patterns against branch names, upstreams and/or refnames")
             (@arg verbose: -v --verbose "List added commits")
+            (@arg patch: -p --patch "List added commits along with their diffs, in the pager")
             (@arg all: -a --all "List all branches")
-            (@arg merged: -m --merged "List only merged branches")
-            (@arg unmerged: -u --unmerged "List only unmerged branches")
+            (@arg sections: --sections requires[all]
+                "With -a, list unmerged and merged branches in separate sections")
+            (@arg merged: -m --merged +takes_value min_values(0)
+                value_name("REF") "List only merged branches (into REF, if it's a ref)")
+            (@arg unmerged: -u --unmerged +takes_value min_values(0)
+                value_name("REF") "List only unmerged branches (into REF, if it's a ref)")
             (@arg reverse: -r --reverse "Reverse listing order")
+            (@arg sort: --sort +takes_value possible_values(&["date", "checkout"])
+                default_value("date") value_name("KEY")
+                "Order branches by last commit, or by when they were last checked out")
+            (@arg date_source: --("date-source") +takes_value
+                possible_values(&["author", "committer"]) default_value("committer")
+                value_name("WHICH") "Date to show ages and sort by")
             (@arg name_only: -n --("name-only") "Print branch names only")
-    )
-    .get_matches();
+            (@arg column: --column requires[name_only]
+                "With -n, lay the names out in columns to fit the terminal")
+            (@arg eval: --eval conflicts_with[name_only]
+                "Print shell assignments B1, B2, ... of the branches listed, for eval")
+            (@arg null: -z requires[name_only] conflicts_with[column]
+                "With -n, end each name with NUL rather than a newline, e.g. for xargs -0")
+            (@arg dirty: --dirty "Mark branches touching files with uncommitted changes")
+            (@arg tree: --tree conflicts_with[stacks]
+                "Group branch names by their slash-separated prefixes")
+            (@arg stacks: --stacks "Show branches built on top of other branches as stacks")
+            (@arg parent: --parent "Guess which local branch each branch was forked from")
+            (@arg release: --release "Show the earliest tag containing each branch")
+            (@arg notes: --notes "Show the first line of the git note on each branch tip")
+            (@arg gerrit: --gerrit
+                "Show the Gerrit changes for the commits on each branch, and count branches whose \
+                 changes are all merged as merged")
+            (@arg prs: --prs
+                "Show the latest pull request from each branch on GitHub, GitLab or Gitea, and count \
+                 branches whose pull request was merged as merged")
+            (@arg issues: --issues
+                "Show the issues (e.g. #123 or JIRA-456) the commits on each branch reference")
+            (@arg trailers: --trailers
+                "Show the trailers (those in bstatus.trailers, or all) on the commits on each branch")
+            (@arg types: --types
+                "Count the conventional-commit types (feat, fix, ...) of the commits on each branch")
+            (@arg bump: --bump
+                "Show the semver bump (major, minor or patch) the conventional commits on each branch imply")
+            (@arg sizes: --sizes
+                "Show how many new objects and bytes the commits on each branch add")
+            (@arg ci: --ci "Show the status of CI on each branch tip: ✓, ✗ or ● (pending)")
+            (@arg base_tag: --("base-tag") +takes_value min_values(0) require_equals(true)
+                value_name("GLOB") "Count commits since the latest (matching) tag")
+            (@arg total: --total +takes_value min_values(0) require_equals(true) value_name("REV")
+                "Also show how many commits each branch has in all (or since REV)")
+            (@arg merges: --merges "Show how many of the commits ahead are merge commits")
+            (@arg fork_point: --("fork-point")
+                "Count commits since where branches forked off, even if it was since rewritten")
+            (@arg first_parent: --("first-parent") "Only follow first parents of merge commits")
+            (@arg max_ahead: --("max-ahead") +takes_value value_name("N") default_value("1000")
+                "Stop counting commits past N (0 for no limit)")
+            (@arg min_ahead: --("min-ahead") +takes_value value_name("N")
+                "List only branches with at least N commits")
+            (@arg max_ahead_filter: --("max-ahead-filter") +takes_value value_name("N")
+                "List only branches with at most N commits")
+            (@arg backend: --backend +takes_value possible_values(backend::NAMES)
+                default_value("libgit2") "Implementation to use for scanning branches")
+            (@arg author: --author +takes_value value_name("PATTERN")
+                "List only branches whose tip was authored or committed by PATTERN")
+            (@arg commit_author: --("commit-author") +takes_value value_name("PATTERN")
+                "With -v or -p, only list commits authored or committed by PATTERN")
+            (@arg grep: --grep +takes_value value_name("PATTERN")
+                "List only branches with a commit whose message contains PATTERN")
+            (@arg mine: --mine
+                "List only branches with commits authored or committed by you (user.email)")
+            (@arg remote: --remote +takes_value value_name("NAME")
+                "List only branches tracking a branch on remote NAME (or 'none' for untracked)")
+            (@arg unpushed: --unpushed
+                "List only branches whose tip isn't on any remote")
+            (@arg pushed: --pushed
+                "Mark branches whose tip isn't on any remote, upstream or not")
+            (@arg resolve_head: --("resolve-head")
+                "Ask the remote for its default branch if there's no local origin/HEAD")
+            (@arg show_signature: --("show-signature") +takes_value min_values(0)
+                require_equals(true) possible_values(&["tip", "all"]) value_name("WHICH")
+                "Check signatures on branch tips, or on all their commits")
+            (@arg recurse_submodules: --("recurse-submodules")
+                "Also list the branches of initialized submodules")
+            (@arg worktrees: --worktrees "Show where branches are checked out in worktrees")
+            (@arg changes: --changes "Summarize uncommitted changes in the default output")
+            (@arg branched: --branched "Show how long ago each branch forked off its base")
+            (@arg visited: --visited "Show how long ago each branch was last checked out")
+            (@arg vs_default: --("vs-default")
+                "Also show how far ahead of and behind the default branch each branch is")
+            (@arg stat_footer: --("stat-footer")
+                "End with how many branches and commits were listed, and how old the oldest is")
+            (@arg json: --json "Print everything as JSON")
+            (@arg schema: --schema "Print the JSON schema of --json output")
+            (@arg vs_remotes: --("vs-remotes")
+                "Show how each branch compares to its counterpart on every remote")
+            (@arg bases: --bases +takes_value +use_delimiter value_name("BRANCHES")
+                conflicts_with[vs_remotes]
+                "Show how far ahead each branch is of each of BRANCHES (e.g. main,develop,release/*)")
+            (@arg fail_if_match: --("fail-if-match")
+                "Exit with status 1 if any branches are listed, e.g. to enforce policies in CI")
+            (@arg fail_if_older_than: --("fail-if-older-than") +takes_value value_name("AGE")
+                "Exit with status 1 if the last commit on any branch listed is older than AGE \
+                 (e.g. 30d, 6months)")
+            (@arg metrics: --metrics +takes_value value_name("FILE")
+                "Write branch counts and ages to FILE for Prometheus' textfile collector")
+            (@arg profile: --profile "Print how long each phase took to stderr")
+            (@arg debug: --debug "Explain how branches are compared (or set BSTATUS_LOG)")
+            (@arg color: --color +takes_value possible_values(&["always", "never", "auto"])
+                default_value("auto") value_name("WHEN")
+                "Colour and align output: always, never, or auto (only on a terminal)")
+            (@subcommand can_switch =>
+                (name: "can-switch")
+                (about: "Check whether a branch can be checked out without conflicts")
+                (@arg BRANCH: +required "Branch to check")
+            )
+            (@subcommand backup =>
+                (about: "Push branches which aren't on any remote to refs/backups/<host>/")
+                (@arg remote: --remote +takes_value +required value_name("NAME")
+                    "Remote to push backups to")
+                (@arg dry_run: -n --("dry-run") "Only print what would be pushed")
+            )
+            (@subcommand restack =>
+                (about: "Rebase branches stacked on other branches onto their parent's new tip")
+                (@arg dry_run: -n --("dry-run") "Only print what would be rebased")
+            )
+            (@subcommand compare =>
+                (about: "Show how far apart two branches are")
+                (@arg A: +required "First branch (or any commit)")
+                (@arg B: +required "Second branch (or any commit)")
+            )
+            (@subcommand config =>
+                (about: "Show the settings git bstatus uses")
+                (@arg show: --show +required "Print each effective setting and where it comes from")
+            )
+            (@subcommand describe =>
+                (about: "Edit a branch's description in $EDITOR")
+                (@arg BRANCH: +required "Branch to describe")
+            )
+            (@subcommand show =>
+                (about: "Show everything known about a branch")
+                (@arg BRANCH: +required "Branch to show")
+                (@arg web: --web "Open the branch on the forge hosting its remote instead")
+            )
+            (@subcommand open =>
+                (about: "Open a branch on the forge hosting its remote (GitHub, GitLab or Gitea)")
+                (@arg BRANCH: +required "Branch to open")
+            )
+            (@subcommand expand =>
+                (about: "Print ARGs with %N replaced by the Nth branch of the last listing")
+                (@arg ARG: +required +multiple "Argument to expand, e.g. %2")
+            )
+            (@subcommand remote_only =>
+                (name: "remote-only")
+                (about: "List remote branches which have no local branch")
+            )
+            (@subcommand setup =>
+                (about: "Set up a git bs alias, shell completions and a prompt snippet")
+                (@arg shell: --shell +takes_value possible_values(&["bash", "zsh", "fish"])
+                    "Shell to set up (defaults to $SHELL)")
+                (@arg yes: -y --yes "Don't ask before each step")
+            )
+    );
+    let matches = app.clone().get_matches();
+
+    // BSTATUS_LOG takes env_logger filters, e.g. "debug" or "trace"
+    let mut logger = env_logger::Builder::from_env(env_logger::Env::new().filter("BSTATUS_LOG"));
+    if matches.is_present("debug") {
+        logger.filter_module(module_path!(), log::LevelFilter::Debug);
+    }
+    logger.format_target(false).init();
+
+    let plain = match matches.value_of("color").unwrap() {
+        "always" => false,
+        "never" => true,
+        _ => !utils::is_fancy(std::io::stdout()),
+    };
+
+    if matches.is_present("schema") {
+        print!("{}", json::SCHEMA);
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("can-switch") {
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir"))
+            .and_then(|repo| switch::can_switch(&repo, sub.value_of("BRANCH").unwrap(), plain));
+        match r {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => fail(e),
+        }
+    }
+
+    if let Some(sub) = matches.subcommand_matches("describe") {
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir"))
+            .and_then(|repo| describe::describe(&repo, sub.value_of("BRANCH").unwrap()));
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("setup") {
+        if let Err(e) = setup::setup(app, sub.value_of("shell"), sub.is_present("yes")) {
+            fail(e);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("expand") {
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir")).and_then(
+            |repo| {
+                let mut stdout = std::io::stdout().lock();
+                for arg in sub.values_of("ARG").unwrap() {
+                    stdout.write_all(&placeholders::expand(&repo, arg)?)?;
+                    stdout.write_all(b"\n")?;
+                }
+                Ok(())
+            },
+        );
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("backup") {
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir")).and_then(
+            |repo| {
+                backup::backup(
+                    &repo,
+                    sub.value_of("remote").unwrap(),
+                    sub.is_present("dry_run"),
+                )
+            },
+        );
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("restack") {
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir")).and_then(
+            |repo| {
+                let (default, _) = find_default_branch(&repo, false)?;
+                restack::restack(&repo, default, sub.is_present("dry_run"))
+            },
+        );
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("compare") {
+        let max_ahead = max_ahead_arg(&matches);
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir")).and_then(
+            |repo| {
+                compare::compare(
+                    &repo,
+                    sub.value_of("A").unwrap(),
+                    sub.value_of("B").unwrap(),
+                    max_ahead,
+                    plain,
+                )
+            },
+        );
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if matches.subcommand_matches("config").is_some() {
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir"))
+            .and_then(|repo| config::show(&repo, plain));
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("open").or_else(|| {
+        matches
+            .subcommand_matches("show")
+            .filter(|sub| sub.is_present("web"))
+    }) {
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir"))
+            .and_then(|repo| forge::url(&repo, sub.value_of("BRANCH").unwrap()))
+            .and_then(|url| forge::browse(&url));
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("show") {
+        let max_ahead = max_ahead_arg(&matches);
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir")).and_then(
+            |repo| {
+                let (default, default_ref) = find_default_branch(&repo, false)?;
+                let default_name = default_ref
+                    .as_deref()
+                    .and_then(|r| r.strip_prefix(LOCAL_BRANCH_REF_PREFIX))
+                    .unwrap_or("the default branch");
+                show::show(
+                    &repo,
+                    sub.value_of("BRANCH").unwrap(),
+                    default,
+                    default_name,
+                    max_ahead,
+                    plain,
+                )
+            },
+        );
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    if matches.subcommand_matches("remote-only").is_some() {
+        let max_ahead = max_ahead_arg(&matches);
+        let r = open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir")).and_then(
+            |repo| {
+                let backend = backend::open(matches.value_of("backend").unwrap(), &repo)?;
+                let (default, _) = find_default_branch(&repo, false)?;
+                remote_only::remote_only(&repo, &*backend, default, max_ahead, plain)
+            },
+        );
+        if let Err(e) = r {
+            fail(e);
+        }
+        return;
+    }
+
+    // `-m` and `-u` take everything up to the next flag; past the ref, those are patterns
+    let merged_args: Vec<&str> = matches
+        .values_of("merged")
+        .or_else(|| matches.values_of("unmerged"))
+        .map_or(Vec::new(), |values| values.collect());
 
     /* just collapse to vector now for later */
-    let maybe_patterns = matches.values_of("BRANCH").map(|values| values.collect());
+    let maybe_patterns: Option<Vec<&str>> = match matches.values_of("BRANCH") {
+        Some(values) => Some(merged_args.iter().skip(1).copied().chain(values).collect()),
+        None if merged_args.len() > 1 => Some(merged_args[1..].to_vec()),
+        None => None,
+    };
 
+    // there's no "recent" summary in JSON; the consumer can do what it wants with everything
     let filter = if matches.is_present("all")
         || (matches.is_present("merged") && matches.is_present("unmerged"))
+        || (matches.is_present("json")
+            && !matches.is_present("merged")
+            && !matches.is_present("unmerged"))
     {
         BranchFilter::All
     } else if matches.is_present("merged") {
@@ -77,324 +663,2894 @@ fn main() {
         BranchFilter::Recent
     };
 
-    let output_mode = if matches.is_present("verbose") {
+    // listing diffs means listing the commits they're for
+    let verbose = matches.is_present("verbose") || matches.is_present("patch");
+    if matches.is_present("commit_author") && !verbose {
+        clap::Error::with_description(
+            "--commit-author filters the commits listed with -v or -p",
+            clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit();
+    }
+
+    let output_mode = if matches.is_present("json") {
+        OutputMode::Json
+    } else if verbose {
         OutputMode::ListingCommits
     } else if matches.is_present("name_only") {
         OutputMode::NameOnly
-    } else if filter != BranchFilter::Recent || maybe_patterns != None {
+    } else if matches.is_present("eval") {
+        OutputMode::Eval
+    } else if matches.is_present("vs_remotes") {
+        OutputMode::RemotesMatrix
+    } else if matches.is_present("bases") {
+        OutputMode::BasesMatrix
+    } else if filter != BranchFilter::Recent || maybe_patterns.is_some() {
         OutputMode::Listing
     } else {
         OutputMode::Human
     };
 
-    if let Err(e) = run(
-        matches.value_of_os("REPO"),
-        &maybe_patterns,
+    let opts = Options {
+        patterns: maybe_patterns,
+        ignore_case: matches.is_present("ignore_case"),
+        fuzzy: matches.is_present("fuzzy"),
+        match_on: matches.values_of("match_on").unwrap().collect(),
         output_mode,
+        bases: matches
+            .values_of("bases")
+            .map_or(Vec::new(), |v| v.collect()),
         filter,
-        matches.is_present("reverse"),
-    ) {
-        eprintln!("{} {}", Colour::Red.bold().paint("error:"), e);
-        std::process::exit(1);
-    }
-}
-
-fn run(
-    repo_path: Option<&OsStr>,
-    maybe_patterns: &Option<Vec<&str>>,
-    output_mode: OutputMode,
-    filter: BranchFilter,
-    reverse: bool,
-) -> Result<(), Box<dyn Error>> {
-    let repo = match repo_path {
-        Some(s) => git2::Repository::discover(s)?,
-        None => git2::Repository::discover(std::env::current_dir()?)?,
+        merged_ref: merged_args.first().copied(),
+        reverse: matches.is_present("reverse"),
+        sort: match matches.value_of("sort").unwrap() {
+            "checkout" => Sort::Checkout,
+            _ => Sort::Date,
+        },
+        author_date: matches.value_of("date_source") == Some("author"),
+        mark_dirty: matches.is_present("dirty"),
+        show_parent: matches.is_present("parent"),
+        show_release: matches.is_present("release"),
+        show_notes: matches.is_present("notes"),
+        gerrit: matches.is_present("gerrit"),
+        prs: matches.is_present("prs"),
+        ci: matches.is_present("ci"),
+        show_issues: matches.is_present("issues"),
+        show_trailers: matches.is_present("trailers"),
+        show_types: matches.is_present("types"),
+        show_bump: matches.is_present("bump"),
+        show_sizes: matches.is_present("sizes"),
+        show_merges: matches.is_present("merges"),
+        base_tag: if matches.is_present("base_tag") {
+            Some(matches.value_of("base_tag").unwrap_or("*"))
+        } else {
+            None
+        },
+        total: if matches.is_present("total") {
+            Some(matches.value_of("total"))
+        } else {
+            None
+        },
+        first_parent: matches.is_present("first_parent"),
+        max_ahead: max_ahead_arg(&matches),
+        min_ahead: if matches.is_present("min_ahead") {
+            Some(value_t!(matches, "min_ahead", usize).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        },
+        max_ahead_filter: if matches.is_present("max_ahead_filter") {
+            Some(value_t!(matches, "max_ahead_filter", usize).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        },
+        backend: matches.value_of("backend").unwrap(),
+        author: matches.value_of("author"),
+        commit_author: matches.value_of("commit_author"),
+        patch: matches.is_present("patch"),
+        stat_footer: matches.is_present("stat_footer"),
+        sections: matches.is_present("sections"),
+        column: matches.is_present("column"),
+        null: matches.is_present("null"),
+        grep: matches.value_of("grep"),
+        mine: matches.is_present("mine"),
+        remote: matches.value_of("remote"),
+        unpushed: matches.is_present("unpushed"),
+        show_pushed: matches.is_present("pushed"),
+        resolve_head: matches.is_present("resolve_head"),
+        fork_point: matches.is_present("fork_point"),
+        vs_default: matches.is_present("vs_default"),
+        show_branched: matches.is_present("branched"),
+        show_visited: matches.is_present("visited"),
+        show_signature: matches.is_present("show_signature"),
+        verify_all: matches.value_of("show_signature") == Some("all"),
+        show_changes: matches.is_present("changes"),
+        show_worktrees: matches.is_present("worktrees"),
+        recurse_submodules: matches.is_present("recurse_submodules"),
+        layout: if matches.is_present("tree") {
+            Layout::Tree
+        } else if matches.is_present("stacks") {
+            Layout::Stacks
+        } else {
+            Layout::Flat
+        },
+        plain,
+        profile: profile::Profile::new(matches.is_present("profile")),
+        fail_if_match: matches.is_present("fail_if_match"),
+        fail_if_older_than: matches.value_of("fail_if_older_than").map(|age| {
+            utils::parse_duration(age).unwrap_or_else(|| {
+                clap::Error::value_validation_auto(format!("invalid age '{}'", age)).exit()
+            })
+        }),
+        matched: Cell::new(0),
+        stale: Cell::new(0),
+        metrics: matches
+            .value_of_os("metrics")
+            .map(|path| metrics::Metrics::new(path.into())),
     };
 
-    let info = scan_branches(&repo, maybe_patterns, filter, reverse)?;
-
-    match output_mode {
-        OutputMode::Human => print_human(&repo, &info)?,
-        OutputMode::NameOnly => info.branches.iter().for_each(|b| println!("{}", b.name)),
-        _ => print_listing(
-            &repo,
-            &info.branches,
-            output_mode == OutputMode::ListingCommits,
-        )?,
+    if let Some(dir) = matches.value_of_os("recursive") {
+        finish(run_recursive(Path::new(dir), &opts), &opts);
+        return;
     }
 
-    Ok(())
+    let r =
+        open_repo(matches.value_of_os("REPO"), matches.value_of_os("git_dir")).and_then(|repo| {
+            if opts.output_mode == OutputMode::Json && opts.recurse_submodules {
+                let mut repos = vec![scan_json(&repo, &opts)];
+                collect_submodules_json(&repo, &opts, &mut repos)?;
+                return json::print(&json::aggregate(repos));
+            }
+            run(&repo, &opts)?;
+            if opts.recurse_submodules {
+                run_submodules(&repo, &opts, 0)?;
+            }
+            Ok(())
+        });
+    finish(r, &opts);
 }
 
-fn scan_branches(
-    repo: &git2::Repository,
-    maybe_patterns: &Option<Vec<&str>>,
-    filter: BranchFilter,
-    reverse: bool,
-) -> Result<BranchesInfo, Box<dyn Error>> {
-    let default_sha = find_default_sha(repo)?;
-
-    let mut n_merged: usize = 0;
-    let mut n_unmerged: usize = 0;
-    let mut branches: Vec<BranchInfo> = Vec::new();
-    for branch in repo.branches(Some(git2::BranchType::Local))? {
-        let (branch, branchtype) = branch?;
-        assert!(branchtype == git2::BranchType::Local);
-
-        let name = branch.name()?.unwrap();
+/// Get `--max-ahead`, where 0 means there's no limit.
+fn max_ahead_arg(matches: &clap::ArgMatches) -> Option<usize> {
+    match value_t!(matches, "max_ahead", usize).unwrap_or_else(|e| e.exit()) {
+        0 => None,
+        n => Some(n),
+    }
+}
 
-        if let Some(ref patterns) = maybe_patterns {
-            if patterns.iter().all(|&p| !name.contains(p)) {
-                continue;
-            }
-        }
+/// Wrap up: report how it went, and exit with 1 if one of the `--fail-if-*` checks failed.
+fn finish(r: Result<()>, opts: &Options) {
+    opts.profile.print();
+    if let Err(e) = r.and_then(|_| match opts.metrics {
+        Some(ref m) => m.write().with_context(|| "writing metrics"),
+        None => Ok(()),
+    }) {
+        fail(e);
+    }
+    if (opts.fail_if_match && opts.matched.get() > 0) || opts.stale.get() > 0 {
+        std::process::exit(1);
+    }
+}
 
-        let commit = branch.get().peel_to_commit()?;
-        let oid = commit.id();
+fn fail(e: Error) -> ! {
+    let red = utils::style(!utils::is_fancy(std::io::stderr()), Colour::Red.bold());
+    eprintln!("{} {}", red.paint("error:"), e);
+    std::process::exit(e.exit_code());
+}
 
-        // use upstream branch if defined, otherwise fallback to default
-        let (upstream, upstream_sha) = if let Ok(b) = branch.upstream() {
-            (
-                Some(b.name()?.unwrap().into()),
-                b.get().peel_to_commit()?.id(),
-            )
-        } else {
-            (None, default_sha)
-        };
+/// Open the repo at `--repo` or `--git-dir` if given, or otherwise find it the way git would,
+/// i.e. from `GIT_DIR` and `GIT_WORK_TREE` or by searching up from the current directory.
+fn open_repo(repo_path: Option<&OsStr>, git_dir: Option<&OsStr>) -> Result<git2::Repository> {
+    let not_a_repo = |r: std::result::Result<git2::Repository, git2::Error>, path: PathBuf| {
+        r.map_err(|e| match e.code() {
+            git2::ErrorCode::NotFound => Error::NotARepo(path),
+            _ => e.into(),
+        })
+    };
 
-        let (ahead, _) = repo.graph_ahead_behind(oid, upstream_sha)?;
+    // an explicit --repo is taken as is, without looking at the environment
+    if let Some(path) = repo_path {
+        return not_a_repo(git2::Repository::discover(path), path.into());
+    }
 
-        let merged = ahead == 0;
-        if merged {
-            n_merged += 1;
-        } else {
-            n_unmerged += 1;
+    let work_tree = std::env::var_os("GIT_WORK_TREE");
+    let git_dir = git_dir.map(|d| d.to_os_string()).or_else(|| {
+        // libgit2 doesn't support GIT_WORK_TREE, so we handle both variables ourselves then
+        work_tree.as_ref().and_then(|_| std::env::var_os("GIT_DIR"))
+    });
+    let repo = match git_dir {
+        Some(dir) => not_a_repo(git2::Repository::open(&dir), dir.into())?,
+        None if work_tree.is_none() => {
+            let path = match std::env::var_os("GIT_DIR") {
+                Some(dir) => dir.into(),
+                None => std::env::current_dir()?,
+            };
+            not_a_repo(git2::Repository::open_from_env(), path)?
         }
-
-        if (filter == BranchFilter::Merged && !merged)
-            || (filter == BranchFilter::Unmerged && merged)
-        {
-            continue;
+        None => {
+            let cwd = std::env::current_dir()?;
+            not_a_repo(git2::Repository::discover(&cwd), cwd)?
         }
-
-        assert!(commit.time().seconds() >= 0);
-        let timestamp = commit.time().seconds() as u64;
-
-        branches.push(BranchInfo {
-            active: branch.is_head(),
-            name: name.into(),
-            summary: commit.summary().unwrap().into(),
-            timestamp_rel: utils::epoch_to_relative_str(timestamp),
-            timestamp,
-            ahead,
-            oid,
-            upstream,
-        });
+    };
+    if let Some(work_tree) = work_tree {
+        repo.set_workdir(Path::new(&work_tree), false)?;
     }
+    Ok(repo)
+}
 
-    // sort by timestamp (most recent first)
-    branches.sort_unstable_by_key(|b| std::u64::MAX - b.timestamp);
-
-    if filter == BranchFilter::Recent {
-        branches.truncate(RECENT_N);
-    }
+fn run(repo: &git2::Repository, opts: &Options) -> Result<()> {
+    let info = scan_branches(repo, opts)?;
 
-    if reverse {
-        branches.reverse();
-    }
+    let _pager = if opts.patch && opts.output_mode == OutputMode::ListingCommits {
+        pager::Pager::start(&repo.config()?)?
+    } else {
+        None
+    };
 
-    Ok(BranchesInfo {
-        branches,
-        n_merged,
-        n_unmerged,
+    opts.profile.time("rendering", || {
+        // the branches listed, in the order they were printed in, for `expand`
+        let mut listed = None;
+        match opts.output_mode {
+            OutputMode::Human => listed = Some(print_human(repo, &info, opts)?),
+            OutputMode::NameOnly if opts.column => print_name_columns(&info.branches)?,
+            OutputMode::NameOnly => {
+                // print the names as is so they can be fed back to git
+                let mut stdout = std::io::stdout().lock();
+                let end: &[u8] = if opts.null { b"\0" } else { b"\n" };
+                for b in &info.branches {
+                    stdout.write_all(&b.raw_name)?;
+                    stdout.write_all(end)?;
+                }
+            }
+            OutputMode::Eval => {
+                let mut stdout = std::io::stdout().lock();
+                for (i, b) in info.branches.iter().enumerate() {
+                    write!(stdout, "B{}=", i + 1)?;
+                    stdout.write_all(&utils::shell_quote(&b.raw_name))?;
+                    stdout.write_all(b"\n")?;
+                }
+                listed = Some(info.branches.iter().collect());
+            }
+            OutputMode::RemotesMatrix => print_remotes_matrix(&info, opts),
+            OutputMode::BasesMatrix => print_bases_matrix(&info, opts),
+            OutputMode::Json => json::print(&json::repo(json::repo_path(repo), &info))?,
+            _ => listed = Some(print_listing(repo, &info.branches, opts)?),
+        }
+        // a detached HEAD is remembered by its commit
+        if let Some(listed) = listed {
+            let names: Vec<Vec<u8>> = listed
+                .iter()
+                .map(|b| {
+                    if b.detached {
+                        b.oid.to_string().into_bytes()
+                    } else {
+                        b.raw_name.clone()
+                    }
+                })
+                .collect();
+            placeholders::save(repo, &names);
+        }
+        match opts.output_mode {
+            OutputMode::Human if opts.stat_footer => {
+                let shown = info.branches.len().min(RECENT_N);
+                print_stat_footer(&info.branches[..shown], &info);
+            }
+            OutputMode::Listing | OutputMode::ListingCommits if opts.stat_footer => {
+                print_stat_footer(&info.branches, &info)
+            }
+            _ => {}
+        }
+        Ok(())
     })
 }
 
-/// Get the default SHA against which comparisons should be made to determine +ahead number.
-/// This is usually "master", or the default branch to check out after cloning.
-fn find_default_sha(repo: &git2::Repository) -> Result<git2::Oid, Box<dyn Error>> {
-    // go through all the remotes, and find which has a HEAD branch
-    // then resolve that to the local branch
-    let mut head_ref: Option<git2::Reference> = None;
-    if let Ok(maybe_refs) = repo.references_glob("refs/remotes/*/HEAD") {
-        for maybe_ref in maybe_refs {
-            let r = maybe_ref?;
-            if !r.is_remote() {
-                continue;
-            }
-
-            let is_origin = r.name().unwrap().starts_with("refs/remotes/origin");
-
-            // this ensures that we prefer "origin"; otherwise, we just fallback to whatever
-            // the last remote with a HEAD is (normally, only one remote --the one used to
-            // clone-- has it)
-            head_ref = Some(r);
-            if is_origin {
+/// Print the branch names in as many columns as fit the terminal, top to bottom then left to
+/// right like `ls` does.
+fn print_name_columns(branches: &[BranchInfo]) -> Result<()> {
+    let widths: Vec<usize> = branches
+        .iter()
+        .map(|b| String::from_utf8_lossy(&b.raw_name).chars().count())
+        .collect();
+    let (rows, column_widths) = utils::column_layout(&widths, utils::term_width(), 2);
+    let mut stdout = std::io::stdout().lock();
+    for row in 0..rows {
+        for (column, width) in column_widths.iter().enumerate() {
+            let i = column * rows + row;
+            stdout.write_all(&branches[i].raw_name)?;
+            if i + rows < branches.len() {
+                write!(stdout, "{:1$}", "", width - widths[i] + 2)?;
+            } else {
                 break;
             }
         }
+        stdout.write_all(b"\n")?;
     }
+    Ok(())
+}
 
-    if let Some(hr) = head_ref {
-        let r = hr.resolve()?;
+/// Sum up the `shown` branches: how many there are, their commits and the oldest one, along with
+/// how many are merged across the whole repo.
+fn print_stat_footer(shown: &[BranchInfo], info: &BranchesInfo) {
+    let ahead: usize = shown.iter().map(|b| b.ahead).sum();
+    println!();
+    print!(
+        "{} {} shown, {} ahead in all",
+        shown.len(),
+        if shown.len() == 1 {
+            "branch"
+        } else {
+            "branches"
+        },
+        utils::plural("commit", ahead as u64)
+    );
+    match shown.iter().map(|b| b.timestamp).min() {
+        Some(oldest) => match utils::epoch_to_relative_str(oldest).as_str() {
+            "now" => println!(", oldest from just now"),
+            age => println!(", oldest {} old", age),
+        },
+        None => println!(),
+    }
+    println!(
+        "{} local branches: {} merged, {} unmerged",
+        info.n_merged + info.n_unmerged,
+        info.n_merged,
+        info.n_unmerged
+    );
+}
 
-        let name = r.name().unwrap();
-        let remote_and_ref = &name["refs/remotes/".len()..];
-        let v: Vec<&str> = remote_and_ref.splitn(2, '/').collect();
-        assert!(v.len() == 2);
-        let branch = v[1];
+/// Run on every repository found under `dir`, each under its own header.
+fn run_recursive(dir: &Path, opts: &Options) -> Result<()> {
+    let mut repos = Vec::new();
+    find_repos(dir, &mut repos).with_context(|| format!("searching {}", dir.display()))?;
 
-        // now find the local branch of the same name
-        if let Ok(b) = repo.find_branch(branch, git2::BranchType::Local) {
-            return Ok(b.get().peel_to_commit()?.id());
+    if opts.output_mode == OutputMode::Json {
+        let mut out = Vec::new();
+        for path in &repos {
+            match git2::Repository::open(path) {
+                Ok(repo) => {
+                    out.push(scan_json(&repo, opts));
+                    if opts.recurse_submodules {
+                        collect_submodules_json(&repo, opts, &mut out)?;
+                    }
+                }
+                Err(e) => out.push(json::repo_error(path.display().to_string(), &e.into())),
+            }
         }
+        return json::print(&json::aggregate(out));
     }
 
-    // no HEAD remote ref, or not connected to a local branch, just guess "master" or "main", and
-    // if that's not it, throw
-    if let Ok(b) = repo.find_branch("master", git2::BranchType::Local) {
-        return Ok(b.get().peel_to_commit()?.id());
-    } else if let Ok(b) = repo.find_branch("main", git2::BranchType::Local) {
-        return Ok(b.get().peel_to_commit()?.id());
+    for (i, path) in repos.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("Repository {}:", path.display());
+        let r = git2::Repository::open(path)
+            .map_err(Error::from)
+            .and_then(|repo| {
+                run(&repo, opts)?;
+                if opts.recurse_submodules {
+                    run_submodules(&repo, opts, 1)?;
+                }
+                Ok(())
+            });
+        // one broken or empty repo shouldn't stop the audit
+        if let Err(e) = r {
+            eprintln!(
+                "{} {}: {}",
+                utils::style(!utils::is_fancy(std::io::stderr()), Colour::Yellow.bold())
+                    .paint("warning:"),
+                path.display(),
+                e
+            );
+        }
     }
-
-    Err(Box::new(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "Couldn't find default branch",
-    )))
+    Ok(())
 }
 
-fn print_human(repo: &git2::Repository, info: &BranchesInfo) -> Result<(), Box<dyn Error>> {
-    let head = repo.head()?;
-    if head.is_branch() {
-        let name = head.name().unwrap();
-        assert!(name.starts_with(LOCAL_BRANCH_REF_PREFIX));
-        println!("On branch {}", &name[LOCAL_BRANCH_REF_PREFIX.len()..]);
-    } else {
-        println!("HEAD detached at {:.8}", head.peel_to_commit()?.id());
+/// Collect the repositories under `dir` (including itself), in path order. Doesn't look inside
+/// repositories, nor follow symlinks.
+fn find_repos(dir: &Path, repos: &mut Vec<PathBuf>) -> Result<()> {
+    // either a working tree, or a bare repo
+    let dotgit = dir.join(".git");
+    if dotgit.exists() || (dir.join("HEAD").is_file() && dir.join("objects").is_dir()) {
+        repos.push(dir.to_path_buf());
+        return Ok(());
     }
 
-    println!(
-        "\
-Recently active branches:
-  (use \"git bstatus -a\" to list all branches)
-  (use \"git bstatus -v\" to list commits)
-"
-    );
-
-    if info.branches.len() < RECENT_N {
-        print_branches(repo, &info.branches, false, true)?;
-    } else {
-        print_branches(repo, &info.branches[..RECENT_N], false, true)?;
+    let mut subdirs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            subdirs.push(entry.path());
+        }
     }
-
-    // not worth printing if there's only master
-    if info.n_unmerged > 0 || info.n_merged > 1 {
-        println!(
-            "
-There are {} local branches ({} merged, {} unmerged).
-  (use \"git bstatus -m\" or \"git bstatus -u\" to list them)\
-",
-            info.n_merged + info.n_unmerged,
-            info.n_merged,
-            info.n_unmerged
-        );
+    subdirs.sort();
+    for subdir in subdirs {
+        // e.g. unreadable directories; just skip those
+        if let Err(Error::Io(e)) = find_repos(&subdir, repos) {
+            if e.kind() != std::io::ErrorKind::PermissionDenied {
+                return Err(Error::Io(e));
+            }
+        }
     }
-
     Ok(())
 }
 
-fn print_listing(
-    repo: &git2::Repository,
-    branches: &[BranchInfo],
-    commits: bool,
-) -> Result<(), Box<dyn Error>> {
-    print_branches(repo, branches, commits, false)?;
-
-    Ok(())
+/// Scan `repo` for JSON output, recording any error in the result rather than failing.
+fn scan_json(repo: &git2::Repository, opts: &Options) -> json::Repo {
+    let path = json::repo_path(repo);
+    match scan_branches(repo, opts) {
+        Ok(info) => json::repo(path, &info),
+        Err(e) => json::repo_error(path, &e),
+    }
 }
 
-fn print_branches(
+/// Like `run_submodules()`, but collect the results for JSON output.
+fn collect_submodules_json(
     repo: &git2::Repository,
-    branches: &[BranchInfo],
-    list_commits: bool,
-    tab: bool,
-) -> Result<(), Box<dyn Error>> {
-    if branches.is_empty() {
-        return Ok(());
+    opts: &Options,
+    out: &mut Vec<json::Repo>,
+) -> Result<()> {
+    for sm in repo.submodules()? {
+        if let Ok(sub) = sm.open() {
+            out.push(scan_json(&sub, opts));
+            collect_submodules_json(&sub, opts, out)?;
+        }
     }
+    Ok(())
+}
 
-    // super wasteful, but meh
-    let max_name_len = branches.iter().map(|b| b.name.len()).max().unwrap();
-    let max_timestamp_len = branches
-        .iter()
-        .map(|b| b.timestamp_rel.len())
-        .max()
-        .unwrap();
-    let max_ahead = branches.iter().map(|b| b.ahead).max().unwrap();
-    let max_ahead_len = utils::count_digits(max_ahead);
+/// Run on every initialized submodule of `repo`, recursively, each under its own header.
+fn run_submodules(repo: &git2::Repository, opts: &Options, depth: usize) -> Result<()> {
+    for sm in repo.submodules()? {
+        // not initialized, or not cloned yet
+        let sub = match sm.open() {
+            Ok(sub) => sub,
+            Err(_) => continue,
+        };
+        println!();
+        println!(
+            "{}",
+            indent(depth, &format!("Submodule {}:", sm.path().display()))
+        );
+        // submodules are often just a detached HEAD without any branches, so don't let one of
+        // them stop us
+        if let Err(e) = run(&sub, opts) {
+            eprintln!(
+                "{} {}: {}",
+                utils::style(!utils::is_fancy(std::io::stderr()), Colour::Yellow.bold())
+                    .paint("warning:"),
+                sm.path().display(),
+                e
+            );
+        }
+        run_submodules(&sub, opts, depth + 1)?;
+    }
+    Ok(())
+}
 
-    // use prefix/suffix since regular paint() conflicts with branch_width
-    let (green_prefix, green_suffix) = (Colour::Green.prefix(), Colour::Green.suffix());
-    let (inert_prefix, inert_suffix) = {
-        let s = Style::default();
-        (s.prefix(), s.suffix())
+fn scan_branches(repo: &git2::Repository, opts: &Options) -> Result<BranchesInfo> {
+    let filter = opts.filter;
+    let backend = backend::open(opts.backend, repo)?;
+    let (default_sha, default_ref) = opts.profile.time("default branch", || {
+        find_default_branch(repo, opts.resolve_head)
+    })?;
+    // bare repos have no working tree to be dirty
+    let dirty = if opts.mark_dirty && !repo.is_bare() {
+        Some(dirty_paths(repo)?)
+    } else {
+        None
     };
 
+    let local_tips = if opts.show_parent {
+        local_branch_tips(repo)?
+    } else {
+        Vec::new()
+    };
+
+    let tags = if opts.show_release {
+        version_sorted_tags(repo, None)?
+    } else {
+        Vec::new()
+    };
+
+    let base_tag = match opts.base_tag {
+        Some(glob) => match version_sorted_tags(repo, Some(glob))?.pop() {
+            Some((tag, oid)) => {
+                debug!("counting commits since tag {}", tag);
+                Some(oid)
+            }
+            None => return Err(Error::NotFound(format!("No tags matching '{}'", glob))),
+        },
+        None => None,
+    };
+
+    // like `git branch --merged`, `-m X` is the ref X if there is one, and otherwise a pattern
+    let mut patterns = opts.patterns.clone();
+    let merged_ref = match opts.merged_ref {
+        Some(r) => match repo.revparse_single(r) {
+            Ok(obj) => Some(obj.peel_to_commit()?.id()),
+            Err(e)
+                if matches!(
+                    e.code(),
+                    git2::ErrorCode::NotFound | git2::ErrorCode::InvalidSpec
+                ) =>
+            {
+                patterns.get_or_insert_with(Vec::new).insert(0, r);
+                None
+            }
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+
+    let total_since = match opts.total {
+        Some(Some(r)) => match repo.revparse_single(r) {
+            Ok(obj) => Some(obj.peel_to_commit()?.id()),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                return Err(Error::NotFound(format!("{} not found", r)))
+            }
+            Err(e) => return Err(e.into()),
+        },
+        _ => None,
+    };
+
+    let identities = if opts.author.is_some() || opts.mine {
+        Some(identity::Identities::new(repo)?)
+    } else {
+        None
+    };
+
+    let my_email = match identities {
+        Some(ref ids) if opts.mine => Some(ids.me(repo)?),
+        _ => None,
+    };
+
+    let shallow = if repo.is_shallow() {
+        shallow_roots(repo)?
+    } else {
+        HashSet::new()
+    };
+
+    let config = repo.config()?.snapshot()?;
+
+    let gerrit = if opts.gerrit {
+        Some(gerrit::Gerrit::new(repo)?)
+    } else {
+        None
+    };
+
+    let issue_pattern = if opts.show_issues {
+        Some(commits::issue_pattern(&config)?)
+    } else {
+        None
+    };
+
+    let declared_bases = bases::declared(repo, &config)?;
+    let base_mappings = bases::mappings(repo, &config)?;
+
+    let trailer_keys = if opts.show_trailers {
+        Some(commits::trailer_keys(&config)?)
+    } else {
+        None
+    };
+
+    let forge = if opts.prs || opts.ci {
+        Some(forge::open(repo)?)
+    } else {
+        None
+    };
+
+    let worktrees = worktree_heads(repo)?;
+
+    let checkouts = if opts.sort == Sort::Checkout || opts.show_visited {
+        checkout_times(repo)?
+    } else {
+        HashMap::new()
+    };
+
+    let remote_tips = if opts.unpushed || opts.show_pushed {
+        remote_tips(repo)?
+    } else {
+        Vec::new()
+    };
+
+    // libgit2 can't walk shallow histories, so just collect everything we can reach instead
+    let mut remote_reachable = HashSet::new();
+    if !shallow.is_empty() {
+        for &tip in &remote_tips {
+            remote_reachable.extend(shallow_ancestors(repo, tip, false, &shallow)?);
+        }
+    }
+
+    let remotes: Vec<String> = if opts.output_mode == OutputMode::RemotesMatrix {
+        repo.remotes()?
+            .iter()
+            .flatten()
+            .map(|name| name.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut matrix_bases = Vec::new();
+    if opts.output_mode == OutputMode::BasesMatrix {
+        for pattern in &opts.bases {
+            let found = bases::resolve(repo, &[pattern.to_string()])?;
+            if found.is_empty() {
+                return Err(Error::NotFound(format!("base {} not found", pattern)));
+            }
+            matrix_bases.extend(found);
+        }
+    }
+
+    let mut n_merged: usize = 0;
+    let mut n_unmerged: usize = 0;
+    let mut branches: Vec<BranchInfo> = Vec::new();
+    for branch in opts.profile.time("refs", || backend.branches())? {
+        let display_name = utils::escape_bytes(&branch.name);
+        let name = display_name.as_str();
+
+        let mut match_score = 0;
+        if let Some(ref patterns) = patterns {
+            let refname = format!("{}{}", LOCAL_BRANCH_REF_PREFIX, name);
+            let candidates: Vec<&str> = opts
+                .match_on
+                .iter()
+                .filter_map(|&on| match on {
+                    "name" => Some(name),
+                    "upstream" => branch.upstream.as_ref().map(|(u, _)| u.as_str()),
+                    _ => Some(refname.as_str()),
+                })
+                .collect();
+            let mut pairs = candidates
+                .iter()
+                .flat_map(|&c| patterns.iter().map(move |&p| (c, p)));
+            let score = if opts.fuzzy {
+                pairs
+                    .filter_map(|(c, p)| pattern::fuzzy(c, p, opts.ignore_case))
+                    .map(|(score, _)| score)
+                    .max()
+            } else {
+                pairs
+                    .find_map(|(c, p)| pattern::find(c, p, opts.ignore_case))
+                    .map(|_| 0)
+            };
+            match score {
+                Some(score) => match_score = score,
+                None => continue,
+            }
+        }
+
+        if let Some(remote) = opts.remote {
+            let tracked = upstream_remote(&config, &branch.name)?;
+            let wanted = match tracked {
+                Some(ref r) => r == remote,
+                None => remote == "none",
+            };
+            if !wanted {
+                continue;
+            }
+        }
+
+        let oid = branch.oid;
+
+        let on_remote = if opts.unpushed || opts.show_pushed {
+            Some(if shallow.is_empty() {
+                is_reachable(repo, oid, &remote_tips)
+                    .with_context(|| format!("looking for {} on remotes", name))?
+            } else {
+                remote_reachable.contains(&oid)
+            })
+        } else {
+            None
+        };
+        if opts.unpushed && on_remote == Some(true) {
+            continue;
+        }
+
+        if let (Some(pattern), Some(ref ids)) = (opts.author, &identities) {
+            if !ids.matches(&repo.find_commit(oid)?, |sig| {
+                identity::contains(sig, pattern)
+            })? {
+                continue;
+            }
+        }
+
+        // use upstream branch if defined, otherwise fallback to the base for branches like it, the
+        // nearest declared base, or the default
+        let configured = match branch.upstream {
+            Some(_) => None,
+            None => configured_base(repo, &config, &branch.name)?,
+        };
+        let candidates = bases::mapped(&base_mappings, name).unwrap_or(&declared_bases);
+        let declared = match (&branch.upstream, &configured) {
+            (None, None) if !candidates.is_empty() => opts
+                .profile
+                .time("merge bases", || {
+                    bases::nearest(repo, candidates, name, oid)
+                })
+                .with_context(|| format!("finding the nearest base of {}", name))?,
+            _ => None,
+        };
+        let configured = configured.or_else(|| declared.map(|b| (b.oid, b.refname.clone())));
+        let (upstream, base) = match (&branch.upstream, &configured) {
+            (Some((ref name, oid)), _) => (Some(name.clone()), *oid),
+            (None, Some((oid, _))) => (None, *oid),
+            (None, None) => (None, default_sha),
+        };
+        match (&upstream, &configured) {
+            (Some(u), _) => debug!("{}: comparing against upstream {}", name, u),
+            (None, Some((_, r))) => debug!("{}: comparing against {} (merge base)", name, r),
+            (None, None) => debug!("{}: comparing against the default branch", name),
+        }
+
+        // this is the same as the ahead count unless we're comparing against a tag, but the
+        // behind count is always new
+        let push_pull = match branch.upstream {
+            Some((_, upstream_oid)) => {
+                let (push, ..) = count_ahead(repo, &*backend, oid, upstream_oid, opts, &shallow)
+                    .with_context(|| format!("counting commits to push on {}", name))?;
+                let (pull, ..) = count_ahead(repo, &*backend, upstream_oid, oid, opts, &shallow)
+                    .with_context(|| format!("counting commits to pull on {}", name))?;
+                (push, pull)
+            }
+            None => (0, 0),
+        };
+
+        let mut vs_remotes = Vec::new();
+        for remote in &remotes {
+            let mut refname = format!("refs/remotes/{}/", remote).into_bytes();
+            refname.extend_from_slice(&branch.name);
+            let remote_oid = match std::str::from_utf8(&refname) {
+                Ok(refname) => match repo.refname_to_id(refname) {
+                    Ok(oid) => Some(oid),
+                    Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                },
+                // libgit2 can't look up refs which aren't valid UTF-8
+                Err(_) => None,
+            };
+            vs_remotes.push(match remote_oid {
+                Some(remote_oid) => {
+                    let (push, ..) = count_ahead(repo, &*backend, oid, remote_oid, opts, &shallow)
+                        .with_context(|| format!("comparing {} to {}", name, remote))?;
+                    let (pull, ..) = count_ahead(repo, &*backend, remote_oid, oid, opts, &shallow)
+                        .with_context(|| format!("comparing {} to {}", name, remote))?;
+                    Some((push, pull))
+                }
+                None => None,
+            });
+        }
+
+        let mut vs_bases = Vec::new();
+        for b in &matrix_bases {
+            vs_bases.push(match repo.merge_base(oid, b.oid) {
+                Ok(_) => Some(
+                    count_ahead(repo, &*backend, oid, b.oid, opts, &shallow)
+                        .with_context(|| format!("comparing {} to {}", name, b.name))?
+                        .0,
+                ),
+                Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+                Err(e) => return Err(e.into()),
+            });
+        }
+
+        // when comparing against a tag, it trumps both
+        let base = match base_tag {
+            Some(tag) => tag,
+            // if it's already merged, there's nothing to see through
+            None if opts.fork_point && oid != base && !repo.graph_descendant_of(base, oid)? => {
+                let base_ref = match branch.upstream {
+                    Some(_) => match std::str::from_utf8(&branch.name) {
+                        Ok(b) => Some(repo.branch_upstream_name(&format!("refs/heads/{}", b))?),
+                        Err(_) => None,
+                    }
+                    .and_then(|buf| buf.as_str().map(String::from)),
+                    None => match configured {
+                        Some((_, ref refname)) => Some(refname.clone()),
+                        None => default_ref.clone(),
+                    },
+                };
+                match base_ref {
+                    Some(base_ref) => {
+                        let fp = opts
+                            .profile
+                            .time("merge bases", || fork_point(repo, oid, &base_ref))
+                            .with_context(|| format!("finding fork point of {}", name))?;
+                        match fp {
+                            Some(fp) => debug!("{}: forked off {} at {:.8}", name, base_ref, fp),
+                            None => debug!("{}: no fork point in {} reflog", name, base_ref),
+                        }
+                        fp.unwrap_or(base)
+                    }
+                    None => base,
+                }
+            }
+            None => base,
+        };
+
+        if let (Some(ref email), Some(ref ids)) = (&my_email, &identities) {
+            let mine = |sig: &git2::Signature| identity::has_email(sig, email);
+            let mine = ids.matches(&repo.find_commit(oid)?, mine)?
+                || ids
+                    .matches_any(repo, oid, base, opts.max_ahead, mine)
+                    .with_context(|| format!("finding authors on {}", name))?;
+            if !mine {
+                continue;
+            }
+        }
+
+        if let Some(pattern) = opts.grep {
+            if !grep_commits(repo, oid, base, pattern, opts)
+                .with_context(|| format!("searching commits on {}", name))?
+            {
+                continue;
+            }
+        }
+
+        let (ahead, ahead_capped, ahead_lower_bound) =
+            count_ahead(repo, &*backend, oid, base, opts, &shallow)
+                .with_context(|| format!("counting commits on {}", name))?;
+
+        if opts.min_ahead.is_some_and(|min| ahead < min)
+            || opts.max_ahead_filter.is_some_and(|max| ahead > max)
+        {
+            continue;
+        }
+
+        let vs_default = if opts.vs_default {
+            let (ahead, ..) = count_ahead(repo, &*backend, oid, default_sha, opts, &shallow)
+                .with_context(|| format!("comparing {} to default branch", name))?;
+            let (behind, ..) = count_ahead(repo, &*backend, default_sha, oid, opts, &shallow)
+                .with_context(|| format!("comparing {} to default branch", name))?;
+            Some((ahead, behind))
+        } else {
+            None
+        };
+
+        let changes = match gerrit {
+            Some(ref gerrit) if ahead > 0 => Some(
+                opts.profile
+                    .time("gerrit", || gerrit.changes(repo, oid, base, opts.max_ahead))
+                    .with_context(|| format!("looking up Gerrit changes for {}", name))?,
+            ),
+            _ => None,
+        };
+
+        // names which aren't valid UTF-8 can't have been pushed anywhere we can look them up
+        let forge_head = match (&forge, std::str::from_utf8(&branch.name)) {
+            (Some(_), Ok(branch_name)) => forge::head(repo, branch_name, oid)?,
+            _ => None,
+        };
+
+        let pull_request = match (&forge, &forge_head) {
+            (Some(forge), Some(head)) if opts.prs && ahead > 0 => opts
+                .profile
+                .time("forge", || forge.pull_requests(head))
+                .with_context(|| format!("looking up pull requests for {}", name))?
+                .into_iter()
+                .next(),
+            _ => None,
+        };
+
+        let merged = match merged_ref {
+            Some(r) => oid == r || repo.graph_descendant_of(r, oid)?,
+            // the commits themselves won't be in the base if Gerrit rebased them on submit, or
+            // the pull request was squashed or rebased
+            None => {
+                ahead == 0
+                    || changes.as_ref().is_some_and(|(changes, covered)| {
+                        *covered && changes.iter().all(|c| c.status == "MERGED")
+                    })
+                    || pull_request
+                        .as_ref()
+                        .is_some_and(|pr| pr.state == forge::State::Merged && pr.head == Some(oid))
+            }
+        };
+        if merged {
+            n_merged += 1;
+        } else {
+            n_unmerged += 1;
+        }
+
+        if (filter == BranchFilter::Merged && !merged)
+            || (filter == BranchFilter::Unmerged && merged)
+        {
+            continue;
+        }
+
+        let touches_dirty = match dirty {
+            Some(ref paths) if ahead > 0 && !paths.is_empty() => opts
+                .profile
+                .time("diffs", || touches_paths(repo, oid, base, paths))
+                .with_context(|| format!("diffing commits on {}", name))?,
+            _ => false,
+        };
+
+        let parent = if opts.show_parent && ahead > 0 {
+            opts.profile
+                .time("merge bases", || {
+                    guess_parent(repo, name, oid, default_sha, &local_tips)
+                })
+                .with_context(|| format!("guessing parent of {}", name))?
+        } else {
+            None
+        };
+
+        let branched = if opts.show_branched && ahead > 0 {
+            match opts
+                .profile
+                .time("merge bases", || repo.merge_base(oid, base))
+            {
+                Ok(mb) => {
+                    let time = commit_time(&repo.find_commit(mb)?, opts);
+                    Some(utils::epoch_to_relative_str(time.max(0) as u64))
+                }
+                // unrelated histories, or the fork is cut off in a shallow clone
+                Err(e) if e.code() == git2::ErrorCode::NotFound || repo.is_shallow() => None,
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            None
+        };
+
+        let merged_base = merged_ref.unwrap_or(base);
+        let merged_into = if filter == BranchFilter::Merged && oid != merged_base {
+            let target = match upstream {
+                _ if merged_ref.is_some() => opts.merged_ref.map(String::from),
+                Some(ref u) => Some(u.clone()),
+                None if base_tag.is_none() && configured.is_none() => default_ref
+                    .as_ref()
+                    .map(|r| r.strip_prefix("refs/heads/").unwrap_or(r).to_string()),
+                None => None,
+            };
+            opts.profile
+                .time("revwalks", || find_merge(repo, oid, merged_base))
+                .with_context(|| format!("finding where {} was merged", name))?
+                .map(|time| (target, utils::epoch_to_relative_str(time.max(0) as u64)))
+        } else {
+            None
+        };
+
+        let total = if opts.total.is_some() {
+            Some(
+                opts.profile
+                    .time("revwalks", || {
+                        count_commits(repo, oid, total_since, opts.first_parent)
+                    })
+                    .with_context(|| format!("counting all commits on {}", name))?,
+            )
+        } else {
+            None
+        };
+
+        let merges = match (opts.show_merges, ahead) {
+            (false, _) => None,
+            (true, 0) => Some(0),
+            (true, _) => Some(
+                opts.profile
+                    .time("revwalks", || {
+                        count_merges(repo, oid, base, opts.first_parent, opts.max_ahead)
+                    })
+                    .with_context(|| format!("counting merges on {}", name))?,
+            ),
+        };
+
+        let size = match (opts.show_sizes, ahead) {
+            (false, _) => None,
+            (true, 0) => Some(sizes::Size::default()),
+            (true, _) => Some(
+                opts.profile
+                    .time("sizes", || {
+                        sizes::new_objects(repo, oid, base, opts.max_ahead)
+                    })
+                    .with_context(|| format!("walking objects on {}", name))?,
+            ),
+        };
+
+        let ci = match (&forge, &forge_head) {
+            (Some(forge), Some(head)) if opts.ci => opts
+                .profile
+                .time("forge", || forge.ci_status(head))
+                .with_context(|| format!("looking up CI status of {}", name))?,
+            _ => None,
+        };
+
+        let want_messages =
+            issue_pattern.is_some() || trailer_keys.is_some() || opts.show_types || opts.show_bump;
+        let messages = if want_messages && ahead > 0 {
+            commits::messages(repo, oid, base, opts.first_parent, opts.max_ahead)
+                .with_context(|| format!("reading commits on {}", name))?
+        } else {
+            Vec::new()
+        };
+        let issues = issue_pattern
+            .as_ref()
+            .map(|pattern| commits::issues(&messages, pattern));
+        let trailers = trailer_keys
+            .as_ref()
+            .map(|keys| commits::trailers(&messages, keys));
+        let types = if opts.show_types {
+            Some(commits::types(&messages))
+        } else {
+            None
+        };
+        let bump = if opts.show_bump {
+            commits::bump(&messages)
+        } else {
+            None
+        };
+
+        let signature = if opts.show_signature {
+            let base = if opts.verify_all { Some(base) } else { None };
+            Some(
+                opts.profile
+                    .time("signatures", || {
+                        signature::verify(repo, oid, base, opts.max_ahead)
+                    })
+                    .with_context(|| format!("checking signatures on {}", name))?,
+            )
+        } else {
+            None
+        };
+
+        let release = if opts.show_release {
+            Some(
+                match find_release(repo, oid, &tags)
+                    .with_context(|| format!("finding release of {}", name))?
+                {
+                    Some(tag) => tag.into(),
+                    None => "unreleased".into(),
+                },
+            )
+        } else {
+            None
+        };
+
+        let note = if opts.show_notes {
+            tip_note(repo, oid).with_context(|| format!("reading note on {}", name))?
+        } else {
+            None
+        };
+
+        // commits from before the epoch are just really old
+        let timestamp = if opts.author_date {
+            branch.author_time
+        } else {
+            branch.time
+        };
+        let timestamp = timestamp.max(0) as u64;
+
+        let mut refname = LOCAL_BRANCH_REF_PREFIX.as_bytes().to_vec();
+        refname.extend_from_slice(&branch.name);
+        let worktree = worktrees.get(&refname).cloned();
+        let last_checkout = checkouts.get(&branch.name).copied();
+
+        branches.push(BranchInfo {
+            active: branch.is_head,
+            raw_name: branch.name,
+            name: display_name,
+            summary: branch.summary,
+            timestamp_rel: utils::epoch_to_relative_str(timestamp),
+            timestamp,
+            ahead,
+            ahead_capped,
+            ahead_lower_bound,
+            total,
+            merges,
+            oid,
+            upstream,
+            push_pull,
+            vs_remotes,
+            vs_bases,
+            touches_dirty,
+            merged,
+            vs_default,
+            branched,
+            merged_into,
+            signature,
+            contained_in: None,
+            detached: false,
+            on_remote,
+            worktree,
+            last_checkout,
+            match_score,
+            parent,
+            base: declared.map(|b| b.name.clone()),
+            release,
+            note,
+            changes: changes.map(|(changes, _)| changes),
+            pull_request,
+            ci,
+            issues,
+            trailers,
+            types,
+            bump,
+            size,
+        });
+    }
+
+    // give a detached HEAD its own row, unless we're only after specific branches
+    let selecting = patterns.is_some()
+        || opts.author.is_some()
+        || opts.grep.is_some()
+        || opts.min_ahead.is_some()
+        || opts.max_ahead_filter.is_some()
+        || opts.mine
+        || opts.remote.is_some()
+        || opts.unpushed;
+    let listing_names = matches!(
+        opts.output_mode,
+        OutputMode::NameOnly
+            | OutputMode::Eval
+            | OutputMode::RemotesMatrix
+            | OutputMode::BasesMatrix
+    );
+    if repo.head_detached()? && !selecting && !listing_names {
+        let commit = repo.head()?.peel_to_commit()?;
+        let oid = commit.id();
+        let base = base_tag.unwrap_or(default_sha);
+        let (ahead, ahead_capped, ahead_lower_bound) =
+            count_ahead(repo, &*backend, oid, base, opts, &shallow)
+                .with_context(|| "counting commits on detached HEAD")?;
+        let merged = match merged_ref {
+            Some(r) => oid == r || repo.graph_descendant_of(r, oid)?,
+            None => ahead == 0,
+        };
+        if !((filter == BranchFilter::Merged && !merged)
+            || (filter == BranchFilter::Unmerged && merged))
+        {
+            let name = format!("(HEAD detached at {:.8})", oid);
+            let timestamp = commit_time(&commit, opts).max(0) as u64;
+            let total = match opts.total {
+                Some(_) => Some(count_commits(repo, oid, total_since, opts.first_parent)?),
+                None => None,
+            };
+            branches.push(BranchInfo {
+                active: true,
+                raw_name: name.clone().into_bytes(),
+                name,
+                summary: utils::commit_summary(&commit),
+                timestamp_rel: utils::epoch_to_relative_str(timestamp),
+                timestamp,
+                ahead,
+                ahead_capped,
+                ahead_lower_bound,
+                total,
+                merges: None,
+                oid,
+                upstream: None,
+                push_pull: (0, 0),
+                vs_remotes: Vec::new(),
+                vs_bases: Vec::new(),
+                touches_dirty: false,
+                merged,
+                vs_default: None,
+                branched: None,
+                merged_into: None,
+                signature: None,
+                contained_in: nearest_containing_branch(repo, &*backend, oid, opts, &shallow)
+                    .with_context(|| "finding branches containing detached HEAD")?,
+                detached: true,
+                on_remote: None,
+                worktree: repo.workdir().map(PathBuf::from),
+                last_checkout: None,
+                match_score: 0,
+                parent: None,
+                base: None,
+                release: None,
+                note: None,
+                changes: None,
+                pull_request: None,
+                ci: None,
+                issues: None,
+                trailers: None,
+                types: None,
+                bump: None,
+                size: None,
+            });
+        }
+    }
+
+    // most recent first
+    match opts.sort {
+        Sort::Date => branches.sort_unstable_by_key(|b| u64::MAX - b.timestamp),
+        // what's checked out now comes first, and what never was goes last
+        Sort::Checkout => branches
+            .sort_unstable_by_key(|b| std::cmp::Reverse((b.active, b.last_checkout, b.timestamp))),
+    }
+    // but best matches before that; the sort is stable, so equally good ones stay in order
+    if opts.fuzzy {
+        branches.sort_by_key(|b| std::cmp::Reverse(b.match_score));
+    }
+
+    // the stale ones are the least recent, so look before they're cut, and likewise give every
+    // branch its gauges to go with the totals
+    if let Some(ref m) = opts.metrics {
+        m.record(json::repo_path(repo), &branches, n_merged, n_unmerged);
+    }
+    if let Some(max_age) = opts.fail_if_older_than {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let stale = branches
+            .iter()
+            .filter(|b| !b.detached && now.saturating_sub(b.timestamp) > max_age)
+            .count();
+        opts.stale.set(opts.stale.get() + stale);
+    }
+
+    if filter == BranchFilter::Recent {
+        branches.truncate(RECENT_N);
+    }
+
+    if opts.reverse {
+        branches.reverse();
+    }
+    if opts.sections {
+        // unmerged first, otherwise keeping the order
+        branches.sort_by_key(|b| b.merged);
+    }
+
+    opts.matched
+        .set(opts.matched.get() + branches.iter().filter(|b| !b.detached).count());
+
+    Ok(BranchesInfo {
+        branches,
+        default_branch: default_ref
+            .as_ref()
+            .and_then(|r| r.strip_prefix(LOCAL_BRANCH_REF_PREFIX))
+            .map(String::from),
+        remotes,
+        bases: matrix_bases.into_iter().map(|b| b.name).collect(),
+        n_merged,
+        n_unmerged,
+    })
+}
+
+/// Get the time of `commit` to go by: when it was committed, or with `--date-source=author`, when
+/// it was authored, which rebases and cherry-picks leave alone.
+fn commit_time(commit: &git2::Commit, opts: &Options) -> i64 {
+    if opts.author_date {
+        commit.author().when().seconds()
+    } else {
+        commit.time().seconds()
+    }
+}
+
+/// Get when each branch was last checked out, from the messages `git checkout` and `git switch`
+/// leave in the HEAD reflog.
+fn checkout_times(repo: &git2::Repository) -> Result<HashMap<Vec<u8>, i64>> {
+    let mut times = HashMap::new();
+    // entries are newest first, so the first one we see for a branch is the one we want
+    for entry in repo.reflog("HEAD")?.iter() {
+        let msg = entry.message_bytes().unwrap_or_default();
+        let moves = match msg.strip_prefix(b"checkout: moving from ") {
+            Some(moves) => moves,
+            None => continue,
+        };
+        // ref names can't contain spaces, so this can't be part of either name
+        if let Some(i) = moves.windows(4).position(|w| w == b" to ") {
+            times
+                .entry(moves[i + 4..].to_vec())
+                .or_insert_with(|| entry.committer().when().seconds());
+        }
+    }
+    Ok(times)
+}
+
+/// Get the branches checked out in the main worktree and all linked worktrees, mapping their full
+/// ref names to the worktree paths.
+fn worktree_heads(repo: &git2::Repository) -> Result<HashMap<Vec<u8>, PathBuf>> {
+    // linked worktrees point to the main repo's git dir, which knows about all of them
+    let main = if repo.is_worktree() {
+        let commondir = std::fs::read_to_string(repo.path().join("commondir"))?;
+        git2::Repository::open(repo.path().join(commondir.trim()))?
+    } else {
+        git2::Repository::open(repo.path())?
+    };
+
+    let mut heads = HashMap::new();
+    let mut add = |r: &git2::Repository| -> Result<()> {
+        let workdir = match r.workdir() {
+            Some(workdir) => workdir,
+            None => return Ok(()),
+        };
+        if let Some(target) = r.find_reference("HEAD")?.symbolic_target_bytes() {
+            heads.insert(target.to_vec(), workdir.to_path_buf());
+        }
+        Ok(())
+    };
+
+    add(&main)?;
+    for name in main.worktrees()?.iter().flatten() {
+        let wt = main.find_worktree(name)?;
+        // e.g. its directory was deleted without pruning it
+        if wt.validate().is_err() {
+            continue;
+        }
+        add(&git2::Repository::open_from_worktree(&wt)?)?;
+    }
+    Ok(heads)
+}
+
+/// Get the tips of all the remote-tracking branches.
+fn remote_tips(repo: &git2::Repository) -> Result<Vec<git2::Oid>> {
+    let mut tips = Vec::new();
+    for maybe_ref in repo.references_glob("refs/remotes/*")? {
+        let r = maybe_ref?;
+        // symbolic refs like origin/HEAD point to another ref we'll see anyway
+        if let Some(oid) = r.target() {
+            tips.push(oid);
+        }
+    }
+    Ok(tips)
+}
+
+/// Check whether `oid` is reachable from any of `tips`.
+fn is_reachable(repo: &git2::Repository, oid: git2::Oid, tips: &[git2::Oid]) -> Result<bool> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(oid)?;
+    for &tip in tips {
+        revwalk.hide(tip)?;
+    }
+    // if anything is left, then at least `oid` itself isn't hidden
+    match revwalk.next() {
+        Some(maybe_oid) => maybe_oid.map(|_| false).map_err(|e| e.into()),
+        None => Ok(true),
+    }
+}
+
+/// Get the remote a local branch is tracking (`.` for a local branch), if any.
+fn upstream_remote(config: &git2::Config, branch: &[u8]) -> Result<Option<String>> {
+    // config keys must be valid UTF-8, so such branches can't be configured to track anything
+    let branch = match std::str::from_utf8(branch) {
+        Ok(branch) => branch,
+        Err(_) => return Ok(None),
+    };
+    match config.get_string(&format!("branch.{}.remote", branch)) {
+        Ok(remote) => Ok(Some(remote)),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Resolve the `branch.<name>.merge` setting of a branch whose upstream couldn't be found, e.g.
+/// because its remote-tracking branch was never fetched, by looking for its remote-tracking
+/// branch or failing that, a local branch of the same name. Returns its tip and full ref name.
+fn configured_base(
+    repo: &git2::Repository,
+    config: &git2::Config,
+    branch: &[u8],
+) -> Result<Option<(git2::Oid, String)>> {
+    let branch = match std::str::from_utf8(branch) {
+        Ok(branch) => branch,
+        Err(_) => return Ok(None),
+    };
+    let merge = match config.get_string(&format!("branch.{}.merge", branch)) {
+        Ok(merge) => merge,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let merge = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+
+    let mut candidates = Vec::new();
+    if let Some(remote) = upstream_remote(config, branch.as_bytes())? {
+        if remote != "." {
+            candidates.push(format!("refs/remotes/{}/{}", remote, merge));
+        }
+    }
+    candidates.push(format!("refs/heads/{}", merge));
+
+    for refname in candidates {
+        match repo.refname_to_id(&refname) {
+            Ok(oid) => return Ok(Some((oid, refname))),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+            // e.g. the merge setting isn't a valid ref name
+            Err(e) if e.code() == git2::ErrorCode::InvalidSpec => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(None)
+}
+
+/// Get the default SHA against which comparisons should be made to determine +ahead number.
+/// This is usually "master", or the default branch to check out after cloning. Also returns its
+/// full ref name if there is one.
+fn find_default_branch(
+    repo: &git2::Repository,
+    resolve_head: bool,
+) -> Result<(git2::Oid, Option<String>)> {
+    let config = repo.config()?;
+
+    // an explicit setting trumps any guessing
+    match config.get_string(config::DEFAULT_BASE) {
+        Ok(base) => {
+            debug!("default branch {} (from bstatus.default-base)", base);
+            return match repo.revparse_ext(&base) {
+                Ok((obj, r)) => Ok((
+                    obj.peel_to_commit()?.id(),
+                    r.and_then(|r| r.name().map(String::from)),
+                )),
+                Err(e) if e.code() == git2::ErrorCode::NotFound => Err(Error::NotFound(format!(
+                    "bstatus.default-base '{}' not found",
+                    base
+                ))),
+                Err(e) => Err(e.into()),
+            };
+        }
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    // go through all the remotes, and find which has a HEAD branch
+    // then resolve that to the local branch
+    let mut head_ref: Option<git2::Reference> = None;
+    if let Ok(maybe_refs) = repo.references_glob("refs/remotes/*/HEAD") {
+        for maybe_ref in maybe_refs {
+            let r = maybe_ref?;
+            if !r.is_remote() {
+                continue;
+            }
+
+            let is_origin = r.name_bytes().starts_with(b"refs/remotes/origin");
+
+            // this ensures that we prefer "origin"; otherwise, we just fallback to whatever
+            // the last remote with a HEAD is (normally, only one remote --the one used to
+            // clone-- has it)
+            head_ref = Some(r);
+            if is_origin {
+                break;
+            }
+        }
+    }
+
+    if let Some(hr) = head_ref {
+        let r = hr.resolve()?;
+
+        // a non-UTF-8 name won't match any local branch below, which is fine
+        let name = String::from_utf8_lossy(r.name_bytes());
+        let remote_and_ref = &name["refs/remotes/".len()..];
+        // now find the local branch of the same name
+        if let Some((_, branch)) = remote_and_ref.split_once('/') {
+            if let Some(found) = local_branch_sha(repo, branch)? {
+                debug!("default branch {} (from {})", branch, name);
+                return Ok(found);
+            }
+            debug!("{} points to {}, which has no local branch", name, branch);
+        }
+    } else if resolve_head {
+        if let Some(branch) = remote::resolve_head(repo)? {
+            if let Some(found) = local_branch_sha(repo, &branch)? {
+                debug!("default branch {} (from the remote's HEAD)", branch);
+                return Ok(found);
+            }
+            debug!("remote HEAD is {}, which has no local branch", branch);
+        }
+    }
+
+    // no HEAD remote ref, or not connected to a local branch; try what new repos default to
+    if let Ok(branch) = config.get_string("init.defaultBranch") {
+        if let Some(found) = local_branch_sha(repo, &branch)? {
+            debug!("default branch {} (from init.defaultBranch)", branch);
+            return Ok(found);
+        }
+    }
+
+    // the branch HEAD pointed to when the repo was created or cloned is likely the main one
+    for maybe_branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = maybe_branch?;
+        let name = match branch.get().name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let reflog = repo.reflog(name)?;
+        // entries are newest first
+        let first = match reflog.iter().next_back() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let msg = first.message_bytes().unwrap_or_default();
+        if msg.starts_with(b"commit (initial):") || msg.starts_with(b"clone: ") {
+            debug!(
+                "default branch {} (the repo was created with it)",
+                name.strip_prefix(LOCAL_BRANCH_REF_PREFIX).unwrap_or(name)
+            );
+            return Ok((branch.get().peel_to_commit()?.id(), Some(name.to_string())));
+        }
+    }
+
+    // if all else fails, just guess "master" or "main", and if that's not it, throw
+    for name in &["master", "main"] {
+        if let Some(found) = local_branch_sha(repo, name)? {
+            debug!("default branch {} (guessed)", name);
+            return Ok(found);
+        }
+    }
+
+    Err(Error::NotFound(
+        "Couldn't find default branch (set bstatus.default-base?)".into(),
+    ))
+}
+
+/// Get the tip and full ref name of local branch `name`, if it exists.
+fn local_branch_sha(
+    repo: &git2::Repository,
+    name: &str,
+) -> Result<Option<(git2::Oid, Option<String>)>> {
+    match repo.find_branch(name, git2::BranchType::Local) {
+        Ok(b) => Ok(Some((
+            b.get().peel_to_commit()?.id(),
+            b.get().name().map(String::from),
+        ))),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Find when `oid` made it into the first-parent history of `base`, i.e. the time of the merge
+/// commit which brought it in, or of the commit itself if it was fast-forwarded.
+fn find_merge(repo: &git2::Repository, oid: git2::Oid, base: git2::Oid) -> Result<Option<i64>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.simplify_first_parent()?;
+    revwalk.push(base)?;
+    let mut chain = Vec::new();
+    for maybe_oid in revwalk {
+        match maybe_oid {
+            Ok(oid) => chain.push(oid),
+            // we can only see so far back in shallow clones
+            Err(_) if repo.is_shallow() => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // commits along the chain contain `oid` up to the point where it was merged, so bisect
+    let contains = |c: git2::Oid| Ok(c == oid || repo.graph_descendant_of(c, oid)?);
+    let (mut lo, mut hi) = (0, chain.len());
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let found = match contains(chain[mid]) {
+            Ok(found) => found,
+            Err(_) if repo.is_shallow() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if found {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    match lo {
+        0 => Ok(None),
+        n => Ok(Some(repo.find_commit(chain[n - 1])?.time().seconds())),
+    }
+}
+
+/// Find where `oid` forked off from `base_ref`, using its reflog to see through rebases and resets
+/// like `git merge-base --fork-point` does.
+fn fork_point(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    base_ref: &str,
+) -> Result<Option<git2::Oid>> {
+    let reflog = repo.reflog(base_ref)?;
+
+    // any past tip of the base that's in the branch's history is a candidate
+    let mut candidates: Vec<git2::Oid> = Vec::new();
+    for entry in reflog.iter() {
+        let c = entry.id_new();
+        if c.is_zero() || candidates.contains(&c) {
+            continue;
+        }
+        if c == oid || repo.graph_descendant_of(oid, c)? {
+            candidates.push(c);
+        }
+    }
+
+    // and the fork point is the one closest to the branch
+    for &c in &candidates {
+        let mut closest = true;
+        for &other in &candidates {
+            if other != c && repo.graph_descendant_of(other, c)? {
+                closest = false;
+                break;
+            }
+        }
+        if closest {
+            return Ok(Some(c));
+        }
+    }
+    Ok(None)
+}
+
+/// Count the commits reachable from `oid` but not from `base`, stopping at `--max-ahead`. Also
+/// returns whether the count was capped, and whether it's only a lower bound because the walk ran
+/// into the edge of a shallow clone (whose `shallow` roots are passed in).
+fn count_ahead(
+    repo: &git2::Repository,
+    backend: &dyn backend::Backend,
+    oid: git2::Oid,
+    base: git2::Oid,
+    opts: &Options,
+    shallow: &HashSet<git2::Oid>,
+) -> Result<(usize, bool, bool)> {
+    let max = opts.max_ahead.unwrap_or(usize::MAX);
+
+    opts.profile.time("revwalks", || {
+        // libgit2 doesn't know how to walk shallow histories, so do it ourselves
+        if !shallow.is_empty() {
+            log::trace!("walking shallow history from {:.8} to {:.8}", oid, base);
+            let theirs = shallow_ancestors(repo, base, opts.first_parent, shallow)?;
+            let ours = shallow_ancestors(repo, oid, opts.first_parent, shallow)?;
+            let ahead: Vec<&git2::Oid> = ours.difference(&theirs).collect();
+            // if we hit the edge, there may be more commits we can't see
+            let lower_bound = ahead.iter().any(|o| shallow.contains(o));
+            return Ok((ahead.len().min(max), ahead.len() > max, lower_bound));
+        }
+
+        let (n, capped) = backend.count_ahead(oid, base, opts.first_parent, opts.max_ahead)?;
+        Ok((n, capped, false))
+    })
+}
+
+/// Get all the commits reachable from `oid` in a shallow clone, without crossing its `shallow`
+/// roots.
+fn shallow_ancestors(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    first_parent: bool,
+    shallow: &HashSet<git2::Oid>,
+) -> Result<HashSet<git2::Oid>> {
+    let mut seen = HashSet::new();
+    let mut queue = vec![oid];
+    while let Some(oid) = queue.pop() {
+        if !seen.insert(oid) || shallow.contains(&oid) {
+            continue;
+        }
+        let commit = repo.find_commit(oid)?;
+        let n_parents = if first_parent { 1 } else { usize::MAX };
+        queue.extend(commit.parent_ids().take(n_parents));
+    }
+    Ok(seen)
+}
+
+/// Get the commits at which the history of a shallow clone was cut off.
+fn shallow_roots(repo: &git2::Repository) -> Result<HashSet<git2::Oid>> {
+    let mut roots = HashSet::new();
+    let contents = match std::fs::read_to_string(repo.path().join("shallow")) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(roots),
+        Err(e) => return Err(e.into()),
+    };
+    for line in contents.lines().filter(|l| !l.is_empty()) {
+        roots.insert(git2::Oid::from_str(line)?);
+    }
+    Ok(roots)
+}
+
+/// Get the set of paths which have uncommitted changes, either staged or not.
+fn dirty_paths(repo: &git2::Repository) -> Result<HashSet<Vec<u8>>> {
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+
+    let mut paths = HashSet::new();
+    for entry in repo.statuses(Some(&mut opts))?.iter() {
+        paths.insert(entry.path_bytes().to_vec());
+    }
+    Ok(paths)
+}
+
+/// Check whether the message of any commit reachable from `oid` but not from `base` contains
+/// `pattern`, looking at no more than `--max-ahead` commits.
+fn grep_commits(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    base: git2::Oid,
+    pattern: &str,
+    opts: &Options,
+) -> Result<bool> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(oid)?;
+    revwalk.hide(base)?;
+    if opts.first_parent {
+        revwalk.simplify_first_parent()?;
+    }
+    for maybe_oid in revwalk.take(opts.max_ahead.unwrap_or(usize::MAX)) {
+        let oid = match maybe_oid {
+            Ok(oid) => oid,
+            // the walk fails at the edge of a shallow clone; there's nothing more to search
+            Err(e) if e.code() == git2::ErrorCode::NotFound && repo.is_shallow() => break,
+            Err(e) => return Err(e.into()),
+        };
+        let commit = repo.find_commit(oid)?;
+        let message = String::from_utf8_lossy(commit.message_bytes());
+        if pattern::find(&message, pattern, opts.ignore_case).is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Count the commits reachable from `oid`, but not from `since` if given. In shallow clones, that's
+/// only as far as history goes.
+fn count_commits(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    since: Option<git2::Oid>,
+    first_parent: bool,
+) -> Result<usize> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(oid)?;
+    if let Some(since) = since {
+        revwalk.hide(since)?;
+    }
+    if first_parent {
+        revwalk.simplify_first_parent()?;
+    }
+    let mut n = 0;
+    for maybe_oid in revwalk {
+        match maybe_oid {
+            Ok(_) => n += 1,
+            Err(e) if e.code() == git2::ErrorCode::NotFound && repo.is_shallow() => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(n)
+}
+
+/// Count the merge commits reachable from `oid` but not from `base`, looking at no more than `max`
+/// commits.
+fn count_merges(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    base: git2::Oid,
+    first_parent: bool,
+    max: Option<usize>,
+) -> Result<usize> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(oid)?;
+    revwalk.hide(base)?;
+    if first_parent {
+        revwalk.simplify_first_parent()?;
+    }
+    let mut n = 0;
+    for maybe_oid in revwalk.take(max.unwrap_or(usize::MAX)) {
+        let oid = match maybe_oid {
+            Ok(oid) => oid,
+            Err(e) if e.code() == git2::ErrorCode::NotFound && repo.is_shallow() => break,
+            Err(e) => return Err(e.into()),
+        };
+        if repo.find_commit(oid)?.parent_count() > 1 {
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+
+/// Check whether any commit reachable from `oid` but not from `base` modifies one of `paths`.
+fn touches_paths(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    base: git2::Oid,
+    paths: &HashSet<Vec<u8>>,
+) -> Result<bool> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(oid)?;
+    revwalk.hide(base)?;
+    for maybe_oid in revwalk {
+        let commit = repo.find_commit(maybe_oid?)?;
+        let diff = match commit_diff(repo, &commit) {
+            Ok(diff) => diff,
+            // in partial clones, trees may only be available from the promisor remote; rather than
+            // fetching them, just assume they don't touch anything
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        for delta in diff.deltas() {
+            let old = delta.old_file().path_bytes();
+            let new = delta.new_file().path_bytes();
+            if old.into_iter().chain(new).any(|p| paths.contains(p)) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Get the diff of `commit` against its first parent. Only trees are looked up, never blobs, so
+/// that this doesn't depend on blobs which may be missing in partial clones.
+fn commit_diff<'r>(
+    repo: &'r git2::Repository,
+    commit: &git2::Commit,
+) -> std::result::Result<git2::Diff<'r>, git2::Error> {
+    let parent_tree = match commit.parent_count() {
+        0 => None,
+        _ => Some(commit.parent(0)?.tree()?),
+    };
+    let mut opts = git2::DiffOptions::new();
+    opts.skip_binary_check(true);
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(&mut opts))
+}
+
+/// Print the diff of `commit` against its first parent, indented under it.
+fn print_patch(repo: &git2::Repository, commit: &git2::Commit, plain: bool) -> Result<()> {
+    let parent_tree = match commit.parent_count() {
+        0 => None,
+        _ => Some(commit.parent(0)?.tree()?),
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        let (prefix, style) = match line.origin() {
+            c @ '+' => (Some(c), utils::style(plain, Colour::Green)),
+            c @ '-' => (Some(c), utils::style(plain, Colour::Red)),
+            c @ ' ' => (Some(c), Style::default()),
+            'F' => (None, utils::style(plain, Style::new().bold())),
+            'H' => (None, utils::style(plain, Colour::Cyan)),
+            _ => (None, Style::default()),
+        };
+        let content = String::from_utf8_lossy(line.content());
+        for l in content.lines() {
+            let l = match prefix {
+                Some(c) => format!("{}{}", c, l),
+                None => l.to_string(),
+            };
+            println!("        {}", style.paint(l));
+        }
+        true
+    })?;
+    Ok(())
+}
+
+/// Get all the tags (optionally matching `glob`) pointing at commits, sorted by version (oldest
+/// first).
+fn version_sorted_tags(
+    repo: &git2::Repository,
+    glob: Option<&str>,
+) -> Result<Vec<(String, git2::Oid)>> {
+    let mut tags = Vec::new();
+    for name in repo.tag_names(glob)?.iter().flatten() {
+        let r = repo.find_reference(&format!("refs/tags/{}", name))?;
+        // skip tags of trees and blobs
+        if let Ok(commit) = r.peel_to_commit() {
+            tags.push((name.to_string(), commit.id()));
+        }
+    }
+    tags.sort_by(|(a, _), (b, _)| utils::version_cmp(a, b));
+    Ok(tags)
+}
+
+/// Get the first line of the note on `oid` in the default notes ref (`refs/notes/commits`, unless
+/// `core.notesRef` says otherwise), if there is one.
+fn tip_note(repo: &git2::Repository, oid: git2::Oid) -> Result<Option<String>> {
+    match repo.find_note(None, oid) {
+        Ok(note) => Ok(note
+            .message_bytes()
+            .split(|&b| b == b'\n')
+            .next()
+            .map(|line| String::from_utf8_lossy(line).trim_end().to_string())),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Find the first tag in `tags` which contains `oid`.
+fn find_release<'a>(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    tags: &'a [(String, git2::Oid)],
+) -> Result<Option<&'a str>> {
+    for (name, tag_oid) in tags {
+        if *tag_oid == oid || repo.graph_descendant_of(*tag_oid, oid)? {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
+
+/// Find the local branch whose tip is the fewest commits ahead of `oid`, among those containing it.
+fn nearest_containing_branch(
+    repo: &git2::Repository,
+    backend: &dyn backend::Backend,
+    oid: git2::Oid,
+    opts: &Options,
+    shallow: &HashSet<git2::Oid>,
+) -> Result<Option<String>> {
+    let mut nearest: Option<(usize, String)> = None;
+    for (name, tip) in local_branch_tips(repo)? {
+        if tip != oid && !repo.graph_descendant_of(tip, oid)? {
+            continue;
+        }
+        let (distance, ..) = count_ahead(repo, backend, tip, oid, opts, shallow)?;
+        if nearest.as_ref().is_none_or(|(d, _)| distance < *d) {
+            nearest = Some((distance, name));
+        }
+    }
+    Ok(nearest.map(|(_, name)| name))
+}
+
+fn local_branch_tips(repo: &git2::Repository) -> Result<Vec<(String, git2::Oid)>> {
+    let mut tips = Vec::new();
+    for branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = branch?;
+        let oid = branch.get().peel_to_commit()?.id();
+        tips.push((utils::escape_bytes(branch.name_bytes()?), oid));
+    }
+    Ok(tips)
+}
+
+/// Guess which of the local branches in `tips` the branch `name` was forked from. If the reflog
+/// recorded which branch it was created from, trust that. Otherwise, pick the branch with which
+/// it has the nearest merge-base, preferring the default branch on ties.
+fn guess_parent(
+    repo: &git2::Repository,
+    name: &str,
+    oid: git2::Oid,
+    default_sha: git2::Oid,
+    tips: &[(String, git2::Oid)],
+) -> Result<Option<String>> {
+    if let Ok(reflog) = repo.reflog(&format!("{}{}", LOCAL_BRANCH_REF_PREFIX, name)) {
+        // entries are newest first, so the creation entry is the last one
+        let created_from = reflog
+            .iter()
+            .next_back()
+            .and_then(|e| e.message().map(String::from))
+            .and_then(|m| m.strip_prefix("branch: Created from ").map(String::from));
+        if let Some(from) = created_from {
+            if from != name && tips.iter().any(|(n, _)| *n == from) {
+                return Ok(Some(from));
+            }
+        }
+    }
+
+    let mut best: Option<((usize, bool, usize), &str)> = None;
+    for (candidate, candidate_oid) in tips {
+        if candidate == name {
+            continue;
+        }
+        match repo.merge_base(oid, *candidate_oid) {
+            Ok(_) => (),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        // i.e. the distances from the merge-base to each tip
+        let (ours, theirs) = repo.graph_ahead_behind(oid, *candidate_oid)?;
+        // we can't have been forked from one of our descendants
+        if ours == 0 {
+            continue;
+        }
+        let key = (ours, *candidate_oid != default_sha, theirs);
+        if best.is_none_or(|(k, _)| key < k) {
+            best = Some((key, candidate));
+        }
+    }
+
+    Ok(best.map(|(_, b)| b.into()))
+}
+
+/// For each branch, find the closest other branch whose tip it is built on, if any. Branches
+/// without commits of their own (e.g. the default branch) are never considered parents since
+/// every branch builds on them.
+fn stack_parents(repo: &git2::Repository, branches: &[BranchInfo]) -> Result<Vec<Option<usize>>> {
+    let mut parents = Vec::new();
     for branch in branches {
-        print!(
-            "{star:>star_width$} {bp}{branch:branch_width$}{bs}  \
-             {ago:>ago_width$} {gp}{ahead:+ahead_width$}{gs}",
-            star = if branch.active { "*" } else { " " },
-            star_width = if tab { 4 } else { 1 },
-            branch = branch.name,
-            branch_width = max_name_len,
-            bp = if branch.active {
-                green_prefix
-            } else {
-                inert_prefix
+        // (commits from the candidate's tip to ours, index) of the closest so far
+        let mut parent: Option<(usize, usize)> = None;
+        for (i, candidate) in branches.iter().enumerate() {
+            if candidate.ahead == 0 || candidate.oid == branch.oid {
+                continue;
+            }
+            let (distance, behind) = repo.graph_ahead_behind(branch.oid, candidate.oid)?;
+            if behind == 0 && parent.is_none_or(|(d, _)| distance < d) {
+                parent = Some((distance, i));
+            }
+        }
+        parents.push(parent.map(|(_, i)| i));
+    }
+    Ok(parents)
+}
+
+/// Print the default output, returning the branches listed in the order they were printed in.
+fn print_human<'a>(
+    repo: &git2::Repository,
+    info: &'a BranchesInfo,
+    opts: &Options,
+) -> Result<Vec<&'a BranchInfo>> {
+    let config = repo.config()?;
+    let sections = human_sections(&config)?;
+    // read before printing anything, so a bad setting doesn't cut the output short
+    let stale = if sections.contains(&Section::Stale) {
+        stale_after(&config)?
+    } else {
+        (0, String::new())
+    };
+    let hints = match config.get_bool(config::HINTS) {
+        Ok(hints) => hints,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => true,
+        Err(e) => return Err(e.into()),
+    };
+    let numbered = numbered_rows(&config)?;
+
+    if repo.is_bare() {
+        // nothing is checked out, and HEAD may well point to a branch which doesn't exist
+        let head = repo.find_reference("HEAD")?;
+        match head.symbolic_target_bytes() {
+            Some(target) => {
+                let target = target.strip_prefix(b"refs/heads/").unwrap_or(target);
+                println!(
+                    "Bare repository (HEAD points to {})",
+                    utils::escape_bytes(target)
+                );
+            }
+            None => println!("Bare repository"),
+        }
+    } else {
+        let head = repo.head()?;
+        if head.is_branch() {
+            println!("On branch {}", utils::escape_bytes(head.shorthand_bytes()));
+        } else {
+            println!("HEAD detached at {:.8}", head.peel_to_commit()?.id());
+        }
+
+        if let Some(state) = describe_state(repo)? {
+            println!("{}", utils::style(opts.plain, Colour::Yellow).paint(state));
+        }
+
+        if opts.show_changes {
+            println!("{}", summarize_changes(repo)?);
+        }
+    }
+
+    let mut listed = Vec::new();
+    let mut first = true;
+    for section in sections {
+        // sections with nothing to say are left out altogether
+        let (headline, section_hints) = match section {
+            Section::Recent => (
+                "Recently active branches:".to_string(),
+                vec![
+                    "use \"git bstatus -a\" to list all branches",
+                    "use \"git bstatus -v\" to list commits",
+                ],
+            ),
+            Section::Stale => match count_stale(repo, stale.0, opts)? {
+                0 => continue,
+                n => (
+                    format!(
+                        "{} {} had no commits in {}.",
+                        n,
+                        if n == 1 {
+                            "branch has"
+                        } else {
+                            "branches have"
+                        },
+                        stale.1
+                    ),
+                    vec!["use \"git bstatus -a -r\" to list the oldest first"],
+                ),
             },
-            bs = if branch.active {
-                green_suffix
-            } else {
-                inert_suffix
+            Section::Stash => match stash_summary(repo)? {
+                Some(summary) => (summary, vec!["use \"git stash list\" to see them"]),
+                None => continue,
             },
+            // not worth printing if there's only master
+            Section::Counts if info.n_unmerged == 0 && info.n_merged <= 1 => continue,
+            Section::Counts => (
+                format!(
+                    "There are {} local branches ({} merged, {} unmerged).",
+                    info.n_merged + info.n_unmerged,
+                    info.n_merged,
+                    info.n_unmerged
+                ),
+                vec!["use \"git bstatus -m\" or \"git bstatus -u\" to list them"],
+            ),
+        };
+
+        if !first {
+            println!();
+        }
+        first = false;
+        println!("{}", headline);
+        if hints {
+            for hint in &section_hints {
+                println!("  ({})", hint);
+            }
+        }
+
+        if section == Section::Recent {
+            if hints {
+                println!();
+            }
+            let n = info.branches.len().min(RECENT_N);
+            let first = numbered.then_some(1);
+            listed = print_branches(repo, &info.branches[..n], false, true, first, opts)?;
+        }
+    }
+
+    Ok(listed)
+}
+
+/// Get whether to number the rows of listings for `expand`, from `bstatus.numbers`.
+fn numbered_rows(config: &git2::Config) -> Result<bool> {
+    match config.get_bool(config::NUMBERS) {
+        Ok(numbered) => Ok(numbered),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get the sections of the default output to print, in order, from `bstatus.human-sections`.
+fn human_sections(config: &git2::Config) -> Result<Vec<Section>> {
+    let names = config::list(config, config::HUMAN_SECTIONS)?;
+    if names.is_empty() {
+        return Ok(vec![Section::Recent, Section::Counts]);
+    }
+    names
+        .iter()
+        .map(|name| match name.as_str() {
+            "recent" => Ok(Section::Recent),
+            "stale" => Ok(Section::Stale),
+            "stash" => Ok(Section::Stash),
+            "counts" => Ok(Section::Counts),
+            _ => Err(Error::BadConfig {
+                key: config::HUMAN_SECTIONS.into(),
+                value: name.clone(),
+                reason: "sections are recent, stale, stash and counts".into(),
+            }),
+        })
+        .collect()
+}
+
+/// Get how long a branch can go without commits before it's stale from `bstatus.stale-after` (3
+/// months by default), in seconds and as given.
+fn stale_after(config: &git2::Config) -> Result<(u64, String)> {
+    let age = match config.get_string(config::STALE_AFTER) {
+        Ok(age) => age,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => "3 months".into(),
+        Err(e) => return Err(e.into()),
+    };
+    match utils::parse_duration(&age) {
+        Some(max_age) => Ok((max_age, age)),
+        None => Err(Error::BadConfig {
+            key: config::STALE_AFTER.into(),
+            value: age,
+            reason: "expected a duration like 3 months or 90d".into(),
+        }),
+    }
+}
+
+/// Count the local branches without commits for longer than `max_age` seconds.
+fn count_stale(repo: &git2::Repository, max_age: u64, opts: &Options) -> Result<usize> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut n = 0;
+    for maybe_branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = maybe_branch?;
+        let commit = branch.get().peel_to_commit()?;
+        if now.saturating_sub(commit_time(&commit, opts).max(0) as u64) > max_age {
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+
+/// Sum up the stashes, e.g. `2 stashes, the latest from 3 days ago: WIP on main: ...`, if there
+/// are any.
+fn stash_summary(repo: &git2::Repository) -> Result<Option<String>> {
+    let reflog = match repo.reflog("refs/stash") {
+        Ok(reflog) => reflog,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let latest = match reflog.get(0) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let age = match utils::epoch_to_relative_str(latest.committer().when().seconds().max(0) as u64)
+        .as_str()
+    {
+        "now" => "just now".to_string(),
+        age => format!("{} ago", age),
+    };
+    Ok(Some(format!(
+        "{}, the latest from {}: {}",
+        utils::plural("stash", reflog.len() as u64),
+        age,
+        latest.message().unwrap_or("").trim()
+    )))
+}
+
+/// Count staged, unstaged, untracked and conflicted files, e.g. "2 staged, 1 untracked".
+fn summarize_changes(repo: &git2::Repository) -> Result<String> {
+    // keep this cheap: untracked directories count as one, and don't look inside submodules or
+    // for renames
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(false)
+        .include_ignored(false)
+        .exclude_submodules(true)
+        .renames_head_to_index(false)
+        .renames_index_to_workdir(false);
+
+    let staged_flags = git2::Status::INDEX_NEW
+        | git2::Status::INDEX_MODIFIED
+        | git2::Status::INDEX_DELETED
+        | git2::Status::INDEX_RENAMED
+        | git2::Status::INDEX_TYPECHANGE;
+    let unstaged_flags = git2::Status::WT_MODIFIED
+        | git2::Status::WT_DELETED
+        | git2::Status::WT_RENAMED
+        | git2::Status::WT_TYPECHANGE;
+
+    let (mut staged, mut unstaged, mut untracked, mut conflicted) = (0, 0, 0, 0);
+    for entry in repo.statuses(Some(&mut opts))?.iter() {
+        let status = entry.status();
+        if status.is_conflicted() {
+            conflicted += 1;
+            continue;
+        }
+        if status.intersects(staged_flags) {
+            staged += 1;
+        }
+        if status.intersects(unstaged_flags) {
+            unstaged += 1;
+        }
+        if status.is_wt_new() {
+            untracked += 1;
+        }
+    }
+
+    let counts: Vec<String> = [
+        (staged, "staged"),
+        (unstaged, "unstaged"),
+        (untracked, "untracked"),
+        (conflicted, "conflicted"),
+    ]
+    .iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, what)| format!("{} {}", n, what))
+    .collect();
+
+    if counts.is_empty() {
+        Ok("Working tree clean".into())
+    } else {
+        Ok(format!("Working tree: {}", counts.join(", ")))
+    }
+}
+
+/// Describe the operation in progress, if any, like `git status` does.
+fn describe_state(repo: &git2::Repository) -> Result<Option<String>> {
+    // these files are only informational, so just skip over anything missing or weird
+    let read = |path: &str| -> Option<String> {
+        let contents = std::fs::read_to_string(repo.path().join(path)).ok()?;
+        let contents = contents.trim();
+        let contents = contents.strip_prefix("refs/heads/").unwrap_or(contents);
+        // shorten object IDs like git does
+        match git2::Oid::from_str(contents) {
+            Ok(oid) if contents.len() == 40 => Some(format!("{:.8}", oid)),
+            _ => Some(contents.to_string()),
+        }
+    };
+
+    let state = match repo.state() {
+        git2::RepositoryState::Clean => return Ok(None),
+        git2::RepositoryState::Merge => match read("MERGE_HEAD") {
+            // there may be multiple heads for octopus merges
+            Some(heads) => format!("Merge in progress (merging {})", heads.replace('\n', ", ")),
+            None => "Merge in progress".into(),
+        },
+        git2::RepositoryState::CherryPick | git2::RepositoryState::CherryPickSequence => {
+            match read("CHERRY_PICK_HEAD") {
+                Some(oid) => format!("Cherry-pick in progress (picking {})", oid),
+                None => "Cherry-pick in progress".into(),
+            }
+        }
+        git2::RepositoryState::Revert | git2::RepositoryState::RevertSequence => {
+            match read("REVERT_HEAD") {
+                Some(oid) => format!("Revert in progress (reverting {})", oid),
+                None => "Revert in progress".into(),
+            }
+        }
+        git2::RepositoryState::Bisect => match read("BISECT_START") {
+            Some(start) => format!("Bisect in progress (started from {})", start),
+            None => "Bisect in progress".into(),
+        },
+        git2::RepositoryState::Rebase
+        | git2::RepositoryState::RebaseInteractive
+        | git2::RepositoryState::RebaseMerge => {
+            let dir = if repo.path().join("rebase-merge").is_dir() {
+                "rebase-merge"
+            } else {
+                "rebase-apply"
+            };
+            let branch = read(&format!("{}/head-name", dir));
+            let onto = read(&format!("{}/onto", dir));
+            match (branch, onto) {
+                (Some(branch), Some(onto)) => {
+                    format!("Rebase in progress (rebasing {} onto {})", branch, onto)
+                }
+                (_, Some(onto)) => format!("Rebase in progress (onto {})", onto),
+                _ => "Rebase in progress".into(),
+            }
+        }
+        git2::RepositoryState::ApplyMailbox | git2::RepositoryState::ApplyMailboxOrRebase => {
+            "git am in progress".into()
+        }
+    };
+    Ok(Some(state))
+}
+
+/// Print a listing, returning the branches in the order they were printed in.
+fn print_listing<'a>(
+    repo: &git2::Repository,
+    branches: &'a [BranchInfo],
+    opts: &Options,
+) -> Result<Vec<&'a BranchInfo>> {
+    let commits = opts.output_mode == OutputMode::ListingCommits;
+    let numbered = numbered_rows(&repo.config()?)?;
+    if !opts.sections {
+        return print_branches(repo, branches, commits, false, numbered.then_some(1), opts);
+    }
+
+    let split = branches.partition_point(|b| !b.merged);
+    let (unmerged, merged) = branches.split_at(split);
+    let mut listed = Vec::new();
+    if !unmerged.is_empty() {
+        println!("Unmerged branches:");
+        listed = print_branches(repo, unmerged, commits, true, numbered.then_some(1), opts)?;
+    }
+    if !merged.is_empty() {
+        if !unmerged.is_empty() {
+            println!();
+        }
+        println!("Merged branches:");
+        let first = numbered.then_some(listed.len() + 1);
+        listed.extend(print_branches(repo, merged, commits, true, first, opts)?);
+    }
+
+    Ok(listed)
+}
+
+/// Print a row for each branch, numbered from `first` if given. Returns the branches in the order
+/// they were printed in, which the layout may have changed.
+fn print_branches<'a>(
+    repo: &git2::Repository,
+    branches: &'a [BranchInfo],
+    list_commits: bool,
+    tab: bool,
+    first: Option<usize>,
+    opts: &Options,
+) -> Result<Vec<&'a BranchInfo>> {
+    if branches.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let identities = match (list_commits, opts.commit_author) {
+        (true, Some(_)) => Some(identity::Identities::new(repo)?),
+        _ => None,
+    };
+
+    // each row is the label for the name column and the branch it's for, if any
+    let rows: Vec<(String, Option<&'a BranchInfo>)> = match opts.layout {
+        Layout::Flat => branches.iter().map(|b| (b.name.clone(), Some(b))).collect(),
+        Layout::Tree => {
+            let names: Vec<&str> = branches.iter().map(|b| b.name.as_str()).collect();
+            utils::tree_rows(&names)
+                .into_iter()
+                .map(|row| match row {
+                    utils::TreeRow::Dir { depth, label } => (indent(depth, &label), None),
+                    utils::TreeRow::Leaf {
+                        depth,
+                        label,
+                        index,
+                    } => (indent(depth, &label), Some(&branches[index])),
+                })
+                .collect()
+        }
+        Layout::Stacks => utils::forest_order(&stack_parents(repo, branches)?)
+            .into_iter()
+            .map(|(depth, i)| (indent(depth, &branches[i].name), Some(&branches[i])))
+            .collect(),
+    };
+
+    // super wasteful, but meh
+    let max_name_len = rows
+        .iter()
+        .filter(|(_, b)| b.is_some())
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap();
+    let max_timestamp_len = branches
+        .iter()
+        .map(|b| b.timestamp_rel.chars().count())
+        .max()
+        .unwrap();
+    let max_ahead = branches.iter().map(|b| b.ahead).max().unwrap();
+    // add 1 for the + sign, and another if we need to mark capped counts
+    let ahead_width =
+        utils::count_digits(max_ahead) + 1 + branches.iter().any(|b| b.ahead_capped) as usize;
+    let total_width = branches
+        .iter()
+        .filter_map(|b| b.total)
+        .map(utils::count_digits)
+        .max()
+        .unwrap_or(0);
+    let vs_default_width = branches
+        .iter()
+        .filter_map(|b| b.vs_default)
+        .map(|(a, b)| utils::divergence_str(a, b).chars().count().max(1))
+        .max()
+        .unwrap_or(0);
+    // when piped, don't line things up
+    let (max_name_len, max_timestamp_len, ahead_width, total_width, vs_default_width) =
+        if opts.plain {
+            (0, 0, 0, 0, 0)
+        } else {
+            (
+                max_name_len,
+                max_timestamp_len,
+                ahead_width,
+                total_width,
+                vs_default_width,
+            )
+        };
+
+    let number_width = first.map_or(0, |first| {
+        utils::count_digits(first + branches.len() - 1) + 2
+    });
+
+    // use prefix/suffix since regular paint() conflicts with the widths
+    let (green, yellow) = (
+        utils::style(opts.plain, Colour::Green),
+        utils::style(opts.plain, Colour::Yellow),
+    );
+    let (green_prefix, green_suffix) = (green.prefix(), green.suffix());
+    let (yellow_prefix, yellow_suffix) = (yellow.prefix(), yellow.suffix());
+
+    let mut listed = Vec::new();
+    for (label, branch) in &rows {
+        if let Some(first) = first {
+            let number = match branch {
+                Some(_) => format!("[{}]", first + listed.len()),
+                None => String::new(),
+            };
+            print!("{:>width$}", number, width = number_width);
+        }
+        let branch = match branch {
+            Some(b) => {
+                listed.push(*b);
+                b
+            }
+            None => {
+                println!(
+                    "{:>star_width$} {}",
+                    "",
+                    label,
+                    star_width = if tab { 4 } else { 1 }
+                );
+                continue;
+            }
+        };
+
+        // make it obvious why a branch matched
+        let style = if branch.active {
+            green
+        } else {
+            Style::default()
+        };
+        let hits = match opts.patterns {
+            Some(ref patterns) if !opts.plain => {
+                pattern::matched(label, patterns, opts.ignore_case, opts.fuzzy)
+            }
+            _ => Vec::new(),
+        };
+
+        print!(
+            "{star:>star_width$} {branch}{pad:pad_width$} {sep}\
+             {ago:>ago_width$} {gp}{ahead:>ahead_width$}{gs}",
+            star = branch.marker(),
+            star_width = if tab { 4 } else { 1 },
+            branch = highlight(label, &hits, style, style.bold().underline()),
+            pad = "",
+            pad_width = max_name_len.saturating_sub(label.chars().count()),
             gp = green_prefix,
             gs = green_suffix,
             ago = branch.timestamp_rel,
             ago_width = max_timestamp_len,
-            ahead = branch.ahead,
-            ahead_width = max_ahead_len + 1, // add 1 for the + sign
+            ahead = format!(
+                "{}{}{}",
+                if branch.ahead_lower_bound { "≥" } else { "+" },
+                branch.ahead,
+                if branch.ahead_capped { "+" } else { "" }
+            ),
+            ahead_width = ahead_width,
+            sep = if opts.plain { "" } else { " " },
         );
 
+        if let Some(total) = branch.total {
+            print!(" of {:>width$}", total, width = total_width);
+        }
+
+        match branch.merges {
+            Some(merges) if merges > 0 => {
+                print!(" ({})", utils::plural("merge", merges as u64))
+            }
+            _ => {}
+        }
+
+        if let Some(sig) = branch.signature {
+            let colour = match sig {
+                '✔' => Colour::Green,
+                '✘' => Colour::Red,
+                _ => Colour::Yellow,
+            };
+            print!(
+                " {}",
+                utils::style(opts.plain, colour).paint(sig.to_string())
+            );
+        }
+
+        if opts.ci {
+            match branch.ci {
+                Some(ci) => {
+                    let colour = match ci {
+                        forge::CiStatus::Success => Colour::Green,
+                        forge::CiStatus::Failure => Colour::Red,
+                        forge::CiStatus::Pending => Colour::Yellow,
+                    };
+                    print!(
+                        " {}",
+                        utils::style(opts.plain, colour).paint(ci.symbol().to_string())
+                    );
+                }
+                // keep the column lined up
+                None => print!(" -"),
+            }
+        }
+
+        match branch.size {
+            Some(size) if size.objects > 0 => print!(
+                " ({}{}, {})",
+                if size.capped { "≥" } else { "" },
+                utils::plural("object", size.objects as u64),
+                sizes::human(size.bytes)
+            ),
+            _ => {}
+        }
+
+        if let Some(ref d) = branch.vs_default {
+            let d = match utils::divergence_str(d.0, d.1) {
+                s if s.is_empty() => "=".into(),
+                s => s,
+            };
+            print!(" {:>width$}", d, width = vs_default_width);
+        }
+
         if let Some(ref b) = branch.upstream {
+            let (push, pull) = branch.push_pull;
+            let divergence = utils::divergence_str(push, pull);
             print!(
-                " {gp}({branch}){gs}",
+                " {gp}({branch}{sep}{divergence}){gs}",
                 gp = green_prefix,
                 gs = green_suffix,
-                branch = b
+                branch = b,
+                sep = if divergence.is_empty() { "" } else { " " },
+                divergence = divergence,
+            );
+        }
+
+        if let Some(ref b) = branch.base {
+            print!(" (vs {})", b);
+        }
+
+        if let Some(ref p) = branch.parent {
+            print!(" (from {})", p);
+        }
+
+        if let Some(ref r) = branch.release {
+            print!(" [{}]", r);
+        }
+
+        if let Some(ref changes) = branch.changes {
+            let changes: Vec<String> = changes
+                .iter()
+                .map(|c| {
+                    let colour = match c.status.as_str() {
+                        "MERGED" => Colour::Green,
+                        "ABANDONED" => Colour::Red,
+                        _ => Colour::Yellow,
+                    };
+                    format!(
+                        "{} {}",
+                        c.number,
+                        utils::style(opts.plain, colour).paint(&c.status)
+                    )
+                })
+                .collect();
+            if !changes.is_empty() {
+                print!(" ({})", changes.join(", "));
+            }
+        }
+
+        match branch.types {
+            Some(ref types) if !types.is_empty() => {
+                let types: Vec<String> = types
+                    .iter()
+                    .map(|(kind, n)| format!("{} {}", n, kind))
+                    .collect();
+                print!(" ({})", types.join(", "));
+            }
+            _ => {}
+        }
+
+        if let Some(bump) = branch.bump {
+            let colour = match bump {
+                commits::Bump::Major => Colour::Red,
+                commits::Bump::Minor => Colour::Yellow,
+                commits::Bump::Patch => Colour::Green,
+            };
+            print!(" {}", utils::style(opts.plain, colour).paint(bump.as_str()));
+        }
+
+        match branch.issues {
+            Some(ref issues) if !issues.is_empty() => print!(" [{}]", issues.join(", ")),
+            _ => {}
+        }
+
+        match branch.trailers {
+            Some(ref trailers) if !trailers.is_empty() => {
+                let trailers: Vec<String> = trailers
+                    .iter()
+                    .map(|(key, values)| format!("{}: {}", key, values.join(", ")))
+                    .collect();
+                print!(" {{{}}}", trailers.join("; "));
+            }
+            _ => {}
+        }
+
+        if let Some(ref pr) = branch.pull_request {
+            let colour = match pr.state {
+                forge::State::Open => Colour::Green,
+                forge::State::Merged => Colour::Purple,
+                forge::State::Closed => Colour::Red,
+                forge::State::Draft => Colour::White,
+            };
+            print!(
+                " (#{} {})",
+                pr.number,
+                utils::style(opts.plain, colour).paint(pr.state.as_str())
+            );
+        }
+
+        if let Some(ref n) = branch.note {
+            print!(" {yp}«{}»{ys}", n, yp = yellow_prefix, ys = yellow_suffix);
+        }
+
+        if let Some(ref b) = branch.contained_in {
+            print!(" (in {})", b);
+        }
+
+        if let (true, Some(path)) = (opts.show_worktrees, &branch.worktree) {
+            // drop the trailing slash libgit2 leaves on workdirs
+            let path = path.components().as_path();
+            print!(" (worktree {})", path.display());
+        }
+
+        if let Some((ref target, ref age)) = branch.merged_into {
+            print!(" (merged");
+            if let Some(target) = target {
+                print!(" into {}", target);
+            }
+            match age.as_str() {
+                "now" => print!(" just now)"),
+                age => print!(" {} ago)", age),
+            }
+        }
+
+        if let Some(ref age) = branch.branched {
+            match age.as_str() {
+                "now" => print!(" (branched just now)"),
+                age => print!(" (branched {} ago)", age),
+            }
+        }
+
+        // the current branch is being visited right now
+        if opts.show_visited && !branch.active {
+            match branch.last_checkout {
+                Some(time) => match utils::epoch_to_relative_str(time.max(0) as u64).as_str() {
+                    "now" => print!(" (visited just now)"),
+                    age => print!(" (visited {} ago)", age),
+                },
+                None => print!(" (never visited)"),
+            }
+        }
+
+        if branch.on_remote == Some(false) {
+            print!(
+                " {yp}(local only){ys}",
+                yp = yellow_prefix,
+                ys = yellow_suffix
             );
         }
 
+        if branch.touches_dirty {
+            print!(" {yp}[dirty]{ys}", yp = yellow_prefix, ys = yellow_suffix);
+        }
+
         if !list_commits {
             println!(" {}", branch.summary);
         } else {
             println!();
 
             let mut revwalk = repo.revwalk()?;
-            revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+            // a topological sort needs the full history, which shallow clones don't have
+            if !repo.is_shallow() {
+                revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+            }
+            if opts.first_parent {
+                revwalk.simplify_first_parent()?;
+            }
             revwalk.push(branch.oid)?;
+            let mut hidden = 0;
             for (i, maybe_oid) in revwalk.enumerate() {
-                let oid = maybe_oid?;
+                let oid = match maybe_oid {
+                    Ok(oid) => oid,
+                    Err(_) if repo.is_shallow() => {
+                        println!("    (history truncated by shallow clone)");
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
                 let commit = repo.find_commit(oid)?;
-                let summary = commit.summary().unwrap();
-                println!("    {:.8} {}", oid, summary);
+                let shown = match (opts.commit_author, &identities) {
+                    (Some(pattern), Some(ids)) => {
+                        ids.matches(&commit, |sig| identity::contains(sig, pattern))?
+                    }
+                    _ => true,
+                };
+                if shown {
+                    let summary = utils::commit_summary(&commit);
+                    let time = commit_time(&commit, opts).max(0) as u64;
+                    match utils::epoch_to_relative_str(time).as_str() {
+                        "now" => println!("    {:.8} {} (just now)", oid, summary),
+                        age => println!("    {:.8} {} ({} ago)", oid, summary, age),
+                    }
+                    // the last commit is just there to show where the branch starts
+                    if opts.patch && i < branch.ahead {
+                        print_patch(repo, &commit, opts.plain)
+                            .with_context(|| format!("diffing {:.8}", oid))?;
+                    }
+                } else if i < branch.ahead {
+                    hidden += 1;
+                }
                 if i >= branch.ahead {
                     break;
                 }
             }
+            if hidden > 0 {
+                println!("    ({} by others)", utils::plural("commit", hidden));
+            }
         }
     }
 
-    Ok(())
+    Ok(listed)
+}
+
+fn print_remotes_matrix(info: &BranchesInfo, opts: &Options) {
+    let cells: Vec<Vec<String>> = info
+        .branches
+        .iter()
+        .map(|b| {
+            b.vs_remotes
+                .iter()
+                .map(|cell| match *cell {
+                    None => "-".into(),
+                    Some((0, 0)) => "=".into(),
+                    Some((push, pull)) => utils::divergence_str(push, pull),
+                })
+                .collect()
+        })
+        .collect();
+    print_matrix(&info.branches, &info.remotes, &cells, opts);
+}
+
+/// Print how far ahead of each base each branch is: `✓` if it's all in there, and `-` if they
+/// have nothing in common.
+fn print_bases_matrix(info: &BranchesInfo, opts: &Options) {
+    let cells: Vec<Vec<String>> = info
+        .branches
+        .iter()
+        .map(|b| {
+            b.vs_bases
+                .iter()
+                .map(|cell| match *cell {
+                    None => "-".into(),
+                    Some(0) => "✓".into(),
+                    Some(ahead) => format!("+{}", ahead),
+                })
+                .collect()
+        })
+        .collect();
+    print_matrix(&info.branches, &info.bases, &cells, opts);
+}
+
+/// Print a table with a row of `cells` for each branch, under `columns`.
+fn print_matrix(
+    branches: &[BranchInfo],
+    columns: &[String],
+    cells: &[Vec<String>],
+    opts: &Options,
+) {
+    if branches.is_empty() || columns.is_empty() {
+        return;
+    }
+
+    let max_name_len = branches
+        .iter()
+        .map(|b| b.name.chars().count())
+        .max()
+        .unwrap();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap()
+        })
+        .collect();
+    // when piped, don't line things up
+    let (max_name_len, widths) = if opts.plain {
+        (0, vec![0; widths.len()])
+    } else {
+        (max_name_len, widths)
+    };
+
+    print!("  {:width$}", "", width = max_name_len);
+    for (column, width) in columns.iter().zip(&widths) {
+        print!("  {:>width$}", column, width = width);
+    }
+    println!();
+
+    for (branch, row) in branches.iter().zip(cells) {
+        print!(
+            "{} {:width$}",
+            branch.marker(),
+            branch.name,
+            width = max_name_len
+        );
+        for (cell, width) in row.iter().zip(&widths) {
+            print!("  {:>width$}", cell, width = width);
+        }
+        println!();
+    }
+}
+
+/// Paint `s` in `style`, except for the characters at the byte offsets in `hits`, which get
+/// `hit_style`.
+fn highlight(s: &str, hits: &[usize], style: Style, hit_style: Style) -> String {
+    let mut runs: Vec<ansi_term::ANSIString> = Vec::new();
+    let mut start = 0;
+    let mut in_hit = false;
+    for (i, _) in s.char_indices() {
+        let is_hit = hits.contains(&i);
+        if is_hit != in_hit && i > start {
+            let style = if in_hit { hit_style } else { style };
+            runs.push(style.paint(&s[start..i]));
+            start = i;
+        }
+        in_hit = is_hit;
+    }
+    let style = if in_hit { hit_style } else { style };
+    runs.push(style.paint(&s[start..]));
+    ansi_term::ANSIStrings(&runs).to_string()
+}
+
+fn indent(depth: usize, s: &str) -> String {
+    format!("{:width$}{}", "", s, width = depth * 2)
 }
@@ -0,0 +1,125 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Gauges in the Prometheus text format, for `--metrics`. The file is meant to be picked up by
+//! node_exporter's textfile collector after scheduled runs.
+
+use crate::error::Result;
+use crate::BranchInfo;
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+struct Repo {
+    path: String,
+    merged: usize,
+    unmerged: usize,
+    /// Name, age in seconds and commits ahead of each branch, even those cut from the listing.
+    branches: Vec<(String, u64, usize)>,
+}
+
+pub struct Metrics {
+    path: PathBuf,
+    repos: RefCell<Vec<Repo>>,
+}
+
+/// Escape a label value as the text format wants it.
+fn label(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Append a gauge called `name` with the given labels and values.
+fn family(out: &mut String, name: &str, help: &str, samples: impl Iterator<Item = (String, u64)>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+impl Metrics {
+    pub fn new(path: PathBuf) -> Self {
+        Metrics {
+            path,
+            repos: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Add the branches listed for the repository at `path`, and how many there are in total.
+    pub fn record(&self, path: String, branches: &[BranchInfo], merged: usize, unmerged: usize) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.repos.borrow_mut().push(Repo {
+            path,
+            merged,
+            unmerged,
+            branches: branches
+                .iter()
+                .filter(|b| !b.detached)
+                .map(|b| (b.name.clone(), now.saturating_sub(b.timestamp), b.ahead))
+                .collect(),
+        });
+    }
+
+    /// Write out everything recorded. The file is replaced atomically, so the collector never sees
+    /// it half-written.
+    pub fn write(&self) -> Result<()> {
+        let repos = self.repos.borrow();
+        let repo_label = |r: &Repo| format!("repo=\"{}\"", label(&r.path));
+        let branch_labels =
+            |r: &Repo, b: &str| format!("repo=\"{}\",branch=\"{}\"", label(&r.path), label(b));
+
+        let mut out = String::new();
+        family(
+            &mut out,
+            "git_branches_total",
+            "Number of local branches.",
+            repos
+                .iter()
+                .map(|r| (repo_label(r), (r.merged + r.unmerged) as u64)),
+        );
+        family(
+            &mut out,
+            "git_branches_merged",
+            "Number of merged local branches.",
+            repos.iter().map(|r| (repo_label(r), r.merged as u64)),
+        );
+        family(
+            &mut out,
+            "git_branches_unmerged",
+            "Number of unmerged local branches.",
+            repos.iter().map(|r| (repo_label(r), r.unmerged as u64)),
+        );
+        family(
+            &mut out,
+            "git_branch_age_seconds",
+            "Time since the last commit on the branch.",
+            repos.iter().flat_map(|r| {
+                r.branches
+                    .iter()
+                    .map(move |(name, age, _)| (branch_labels(r, name), *age))
+            }),
+        );
+        family(
+            &mut out,
+            "git_branch_ahead_commits",
+            "Number of commits on the branch which aren't merged.",
+            repos.iter().flat_map(|r| {
+                r.branches
+                    .iter()
+                    .map(move |(name, _, ahead)| (branch_labels(r, name), *ahead as u64))
+            }),
+        );
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, out)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Sending long output through a pager like git does, for `--patch`.
+
+use crate::error::{Error, Result};
+use std::io::Write;
+
+/// Find the pager the way git does: `GIT_PAGER`, `core.pager`, `PAGER`, then less. None if it's
+/// turned off by setting it to `cat` or nothing.
+fn command(config: &git2::Config) -> Result<Option<String>> {
+    let pager = match std::env::var("GIT_PAGER") {
+        Ok(pager) => pager,
+        Err(_) => match config.get_string("core.pager") {
+            Ok(pager) => pager,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                std::env::var("PAGER").unwrap_or_else(|_| "less".into())
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+    match pager.trim() {
+        "" | "cat" => Ok(None),
+        _ => Ok(Some(pager)),
+    }
+}
+
+/// A pager our stdout goes to until this is dropped.
+pub struct Pager {
+    child: std::process::Child,
+    #[cfg(unix)]
+    saved_stdout: libc::c_int,
+}
+
+impl Pager {
+    /// Start sending stdout to the pager, if it's a terminal and there's a pager to use.
+    #[cfg(unix)]
+    pub fn start(config: &git2::Config) -> Result<Option<Pager>> {
+        use std::io::IsTerminal;
+        use std::os::unix::io::AsRawFd;
+
+        if !std::io::stdout().is_terminal() {
+            return Ok(None);
+        }
+        let pager = match command(config)? {
+            Some(pager) => pager,
+            None => return Ok(None),
+        };
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c")
+            .arg(&pager)
+            .stdin(std::process::Stdio::piped());
+        // same defaults as git: quit if it fits on a screen, keep colours and the screen as is
+        if std::env::var_os("LESS").is_none() {
+            cmd.env("LESS", "FRX");
+        }
+        if std::env::var_os("LV").is_none() {
+            cmd.env("LV", "-c");
+        }
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| Error::Command(format!("running {}: {}", pager, e)))?;
+        let stdin = child.stdin.take().unwrap();
+
+        std::io::stdout().flush()?;
+        // SAFETY: plain fd juggling; `stdin` stays open until the dup2 is done
+        let saved_stdout = unsafe {
+            let saved = libc::dup(1);
+            if saved < 0 || libc::dup2(stdin.as_raw_fd(), 1) < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            // like git, just stop once whoever reads our output has seen enough
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+            saved
+        };
+        drop(stdin);
+        Ok(Some(Pager {
+            child,
+            saved_stdout,
+        }))
+    }
+
+    /// There's no way to redirect stdout here, so just print as is.
+    #[cfg(not(unix))]
+    pub fn start(config: &git2::Config) -> Result<Option<Pager>> {
+        let _ = command(config)?;
+        Ok(None)
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        // hand the terminal back, which also lets the pager see the end of its input
+        #[cfg(unix)]
+        unsafe {
+            libc::dup2(self.saved_stdout, 1);
+            libc::close(self.saved_stdout);
+        }
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,201 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Matching the branch patterns given on the command line.
+
+use std::ops::Range;
+
+/// Whether to ignore case when looking for `pattern`: always with `-i`, and otherwise only if the
+/// pattern is all lowercase ("smart case").
+fn ignores_case(pattern: &str, ignore_case: bool) -> bool {
+    ignore_case || !pattern.chars().any(char::is_uppercase)
+}
+
+fn chars_eq(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || (ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+/// Find `pattern` in `s`, returning the byte range it matched.
+pub fn find(s: &str, pattern: &str, ignore_case: bool) -> Option<Range<usize>> {
+    let ignore_case = ignores_case(pattern, ignore_case);
+    for (start, _) in s.char_indices() {
+        let mut chars = s[start..].char_indices();
+        let mut end = start;
+        let found = pattern.chars().all(|p| match chars.next() {
+            Some((i, c)) if chars_eq(c, p, ignore_case) => {
+                end = start + i + c.len_utf8();
+                true
+            }
+            _ => false,
+        });
+        if found {
+            return Some(start..end);
+        }
+    }
+    // the empty pattern matches anything, even the empty string
+    if pattern.is_empty() {
+        Some(0..0)
+    } else {
+        None
+    }
+}
+
+/// Whether `s[i]` starts a word, i.e. a path component or a dash-separated part.
+fn word_start(s: &[char], i: usize) -> bool {
+    i == 0 || matches!(s[i - 1], '/' | '-' | '_' | '.')
+}
+
+/// Match `pattern` against `s` as a subsequence, e.g. `usrlgn` in `feature/user-login`. Returns
+/// how good a match it is (higher is better), favouring consecutive characters and the starts of
+/// words, and the byte offsets of the matched characters.
+pub fn fuzzy(s: &str, pattern: &str, ignore_case: bool) -> Option<(i64, Vec<usize>)> {
+    const MATCH: i64 = 1;
+    const WORD_START: i64 = 8;
+    const CONSECUTIVE: i64 = 4;
+    const GAP: i64 = 1;
+
+    let ignore_case = ignores_case(pattern, ignore_case);
+    let (offsets, chars): (Vec<usize>, Vec<char>) = s.char_indices().unzip();
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    // best[j][i] is the best score for matching the first j + 1 characters of the pattern, with
+    // the last one at s[i], along with where the one before it was matched
+    let mut best: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; chars.len()]; pattern.len()];
+    for (j, &p) in pattern.iter().enumerate() {
+        for (i, &c) in chars.iter().enumerate() {
+            if !chars_eq(c, p, ignore_case) {
+                continue;
+            }
+            let bonus = MATCH + if word_start(&chars, i) { WORD_START } else { 0 };
+            best[j][i] = if j == 0 {
+                Some((bonus, 0))
+            } else {
+                (0..i)
+                    .filter_map(|k| best[j - 1][k].map(|(score, _)| (score, k)))
+                    .map(|(score, k)| {
+                        let link = if k + 1 == i {
+                            CONSECUTIVE
+                        } else {
+                            -GAP * (i - k - 1) as i64
+                        };
+                        (score + link + bonus, k)
+                    })
+                    .max_by_key(|&(score, k)| (score, std::cmp::Reverse(k)))
+            };
+        }
+    }
+
+    let last = pattern.len() - 1;
+    let (mut i, (score, _)) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(i, b)| b.map(|b| (i, b)))
+        .max_by_key(|&(i, (score, _))| (score, std::cmp::Reverse(i)))?;
+    let mut positions = vec![offsets[i]];
+    for j in (1..=last).rev() {
+        i = best[j][i].unwrap().1;
+        positions.push(offsets[i]);
+    }
+    positions.reverse();
+    Some((score, positions))
+}
+
+/// Get the byte offsets of the characters in `s` which any of `patterns` matched, in order, so
+/// they can be highlighted.
+pub fn matched(s: &str, patterns: &[&str], ignore_case: bool, fuzzy: bool) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    for p in patterns {
+        if fuzzy {
+            if let Some((_, positions)) = self::fuzzy(s, p, ignore_case) {
+                offsets.extend(positions);
+            }
+        } else if let Some(range) = find(s, p, ignore_case) {
+            offsets.extend(
+                s[range.clone()]
+                    .char_indices()
+                    .map(|(i, _)| range.start + i),
+            );
+        }
+    }
+    offsets.sort_unstable();
+    offsets.dedup();
+    offsets
+}
+
+/// Whether `s` matches the shell-style `pattern`, where `*` matches anything (slashes included) and
+/// `?` any single character.
+pub fn glob(pattern: &str, s: &str) -> bool {
+    let (p, s): (Vec<char>, Vec<char>) = (pattern.chars().collect(), s.chars().collect());
+    // where to resume if what follows the last `*` doesn't pan out
+    let (mut pi, mut si, mut star) = (0, 0, None);
+    while si < s.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, si));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == s[si] => {
+                pi += 1;
+                si += 1;
+            }
+            _ => match star {
+                Some((star_pi, star_si)) => {
+                    pi = star_pi + 1;
+                    si = star_si + 1;
+                    star = Some((star_pi, star_si + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[test]
+fn test_glob() {
+    assert!(glob("feature/*", "feature/a/b"));
+    assert!(glob("*fix*", "hotfix/x"));
+    assert!(glob("v?", "v2"));
+    assert!(glob("main", "main"));
+    assert!(!glob("main", "main2"));
+    assert!(!glob("feature/*", "features/a"));
+    assert!(!glob("a*b", "acbd"));
+}
+
+#[test]
+fn test_find() {
+    assert_eq!(Some(8..12), find("feature/User", "user", false));
+    assert_eq!(None, find("feature/user", "User", false));
+    assert_eq!(Some(8..12), find("feature/user", "User", true));
+    assert_eq!(Some(0..5), find("Café-x", "CAFÉ", true));
+    assert_eq!(Some(0..0), find("", "", false));
+    assert_eq!(None, find("fix", "fixup", false));
+}
+
+#[test]
+fn test_matched() {
+    assert_eq!(
+        vec![0, 1, 4, 5, 6, 7],
+        matched("fix/bar-baz", &["ba", "fi", "r-"], false, false)
+    );
+    assert_eq!(vec![0, 4], matched("fix/bar", &["fb"], false, true));
+    assert!(matched("fix/bar", &["qux"], false, false).is_empty());
+}
+
+#[test]
+fn test_fuzzy() {
+    assert_eq!(
+        Some(vec![8, 9, 11, 13, 15, 17]),
+        fuzzy("feature/user-login", "usrlgn", false).map(|(_, p)| p)
+    );
+    assert_eq!(None, fuzzy("feature/user-login", "nigol", false));
+    // consecutive characters at the start of a word beat scattered ones
+    let (login, _) = fuzzy("feature/login", "login", false).unwrap();
+    let (scattered, _) = fuzzy("l-o-g-i-n", "login", false).unwrap();
+    let (buried, _) = fuzzy("xloxgxixn", "login", false).unwrap();
+    assert!(login > buried && scattered > buried);
+}
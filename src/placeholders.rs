@@ -0,0 +1,63 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Remembering the order branches were last listed in `.git/bstatus/last-listing`, so `expand`
+//! can turn `%2` into the second one for other git commands.
+
+use crate::error::{Error, Result};
+use log::debug;
+use std::io::Write;
+
+fn path(repo: &git2::Repository) -> std::path::PathBuf {
+    repo.path().join("bstatus").join("last-listing")
+}
+
+/// Remember `names` as the last listing, one per line; ref names can't contain newlines. Failing
+/// to isn't worth an error, e.g. in a repo we can only read.
+pub fn save(repo: &git2::Repository, names: &[Vec<u8>]) {
+    let path = path(repo);
+    let write = || -> std::io::Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        for name in names {
+            file.write_all(name)?;
+            file.write_all(b"\n")?;
+        }
+        std::fs::rename(&tmp, &path)
+    };
+    if let Err(e) = write() {
+        debug!("couldn't save {}: {}", path.display(), e);
+    }
+}
+
+/// Replace `%N` with the Nth branch of the last listing. Anything else is returned as is, so
+/// every argument of a command can go through this.
+pub fn expand(repo: &git2::Repository, arg: &str) -> Result<Vec<u8>> {
+    let n: usize = match arg.strip_prefix('%').and_then(|n| n.parse().ok()) {
+        Some(n) => n,
+        None => return Ok(arg.as_bytes().to_vec()),
+    };
+    let listing = match std::fs::read(path(repo)) {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::NotFound(format!(
+                "no branches listed yet to expand {} from",
+                arg
+            )))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    match n
+        .checked_sub(1)
+        .and_then(|i| listing.split(|&b| b == b'\n').nth(i))
+    {
+        Some(name) if !name.is_empty() => Ok(name.to_vec()),
+        _ => Err(Error::NotFound(format!(
+            "there's no {} in the last listing",
+            arg
+        ))),
+    }
+}
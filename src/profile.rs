@@ -0,0 +1,76 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Where the time goes, for `--profile`.
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+pub struct Profile {
+    /// When we started, or `None` if we're not profiling.
+    start: Option<Instant>,
+    /// Time spent in each phase and how many times we went through it, in first-seen order.
+    phases: RefCell<Vec<(&'static str, Duration, usize)>>,
+}
+
+impl Profile {
+    pub fn new(enabled: bool) -> Self {
+        Profile {
+            start: if enabled { Some(Instant::now()) } else { None },
+            phases: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Run `f`, adding the time it took to `phase`.
+    pub fn time<T>(&self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        if self.start.is_none() {
+            return f();
+        }
+
+        let start = Instant::now();
+        let r = f();
+        let elapsed = start.elapsed();
+
+        let mut phases = self.phases.borrow_mut();
+        match phases.iter_mut().find(|(name, ..)| *name == phase) {
+            Some((_, total, calls)) => {
+                *total += elapsed;
+                *calls += 1;
+            }
+            None => phases.push((phase, elapsed, 1)),
+        }
+        r
+    }
+
+    /// Print the breakdown to stderr, so it doesn't get mixed up with the actual output.
+    pub fn print(&self) {
+        let start = match self.start {
+            Some(start) => start,
+            None => return,
+        };
+
+        let phases = self.phases.borrow();
+        let width = phases
+            .iter()
+            .map(|(name, ..)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, total, calls) in phases.iter() {
+            eprintln!(
+                "{:width$}  {:>10.2?}  ({} {})",
+                name,
+                total,
+                calls,
+                if *calls == 1 { "call" } else { "calls" },
+                width = width
+            );
+        }
+        eprintln!(
+            "{:width$}  {:>10.2?}",
+            "total",
+            start.elapsed(),
+            width = width
+        );
+    }
+}
@@ -0,0 +1,77 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::error::{Context, Result};
+use std::io::{BufRead, IsTerminal, Write};
+
+/// Ask the remote (preferably origin) which branch its HEAD points to, and offer to record it as
+/// `refs/remotes/<remote>/HEAD` like `git remote set-head --auto` would. Returns the branch name.
+pub fn resolve_head(repo: &git2::Repository) -> Result<Option<String>> {
+    let remotes = repo.remotes()?;
+    let name = match remotes.iter().flatten().find(|&r| r == "origin") {
+        Some(name) => name,
+        None => match remotes.iter().flatten().next() {
+            Some(name) => name,
+            None => return Ok(None),
+        },
+    };
+
+    let mut remote = repo.find_remote(name)?;
+    let branch = {
+        let conn = remote
+            .connect_auth(git2::Direction::Fetch, Some(callbacks(repo)?), None)
+            .with_context(|| format!("connecting to {}", name))?;
+        match conn.default_branch() {
+            Ok(buf) => String::from_utf8_lossy(&buf).into_owned(),
+            // the remote may not advertise one, e.g. if its HEAD is detached
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let branch = match branch.strip_prefix("refs/heads/") {
+        Some(branch) => branch.to_string(),
+        None => return Ok(None),
+    };
+
+    let target = format!("refs/remotes/{}/{}", name, branch);
+    if repo.find_reference(&target).is_ok() && offer(name, &branch)? {
+        let head = format!("refs/remotes/{}/HEAD", name);
+        repo.reference_symbolic(&head, &target, false, "bstatus: set remote HEAD")?;
+    }
+
+    Ok(Some(branch))
+}
+
+/// Prompt whether to record `remote`'s HEAD branch, if there's someone to ask.
+fn offer(remote: &str, branch: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        eprintln!(
+            "hint: run \"git remote set-head {} {}\" to remember the default branch",
+            remote, branch
+        );
+        return Ok(false);
+    }
+
+    eprint!("Record {}/HEAD -> {}/{}? [y/N] ", remote, remote, branch);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Authenticate the same way git would by default: through the SSH agent or credential helpers.
+fn callbacks(repo: &git2::Repository) -> Result<git2::RemoteCallbacks<'static>> {
+    let config = repo.config()?;
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            git2::Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            git2::Cred::credential_helper(&config, url, username)
+        } else {
+            git2::Cred::default()
+        }
+    });
+    Ok(callbacks)
+}
@@ -0,0 +1,126 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::backend::Backend;
+use crate::error::{Context, Result};
+use crate::utils;
+use ansi_term::Colour;
+use std::collections::HashSet;
+
+struct RemoteBranch {
+    name: String,
+    timestamp: i64,
+    ahead: usize,
+    ahead_capped: bool,
+    summary: String,
+}
+
+/// List remote-tracking branches which have no local branch of the same name, along with how
+/// far ahead of `default` they are.
+pub fn remote_only(
+    repo: &git2::Repository,
+    backend: &dyn Backend,
+    default: git2::Oid,
+    max_ahead: Option<usize>,
+    plain: bool,
+) -> Result<()> {
+    let mut locals: HashSet<Vec<u8>> = HashSet::new();
+    for maybe_branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = maybe_branch?;
+        locals.insert(branch.name_bytes()?.to_vec());
+    }
+
+    // remote names may themselves contain slashes, so match against the known prefixes
+    let prefixes: Vec<String> = repo
+        .remotes()?
+        .iter()
+        .flatten()
+        .map(|remote| format!("{}/", remote))
+        .collect();
+
+    let mut branches: Vec<RemoteBranch> = Vec::new();
+    for maybe_ref in repo.references_glob("refs/remotes/*")? {
+        let r = maybe_ref?;
+        // skip symbolic refs like origin/HEAD
+        if r.kind() != Some(git2::ReferenceType::Direct) {
+            continue;
+        }
+        let shorthand = r.shorthand_bytes();
+        let local = match prefixes
+            .iter()
+            .filter(|p| shorthand.starts_with(p.as_bytes()))
+            .max_by_key(|p| p.len())
+        {
+            Some(prefix) => &shorthand[prefix.len()..],
+            // leftovers from a deleted remote
+            None => continue,
+        };
+        if locals.contains(local) {
+            continue;
+        }
+
+        let name = utils::escape_bytes(shorthand);
+        let commit = r.peel_to_commit()?;
+        let (ahead, ahead_capped) = backend
+            .count_ahead(commit.id(), default, false, max_ahead)
+            .with_context(|| format!("counting commits on {}", name))?;
+        branches.push(RemoteBranch {
+            name,
+            timestamp: commit.time().seconds(),
+            ahead,
+            ahead_capped,
+            summary: utils::commit_summary(&commit),
+        });
+    }
+
+    if branches.is_empty() {
+        return Ok(());
+    }
+
+    branches.sort_by_key(|b| std::cmp::Reverse(b.timestamp));
+
+    let ages: Vec<String> = branches
+        .iter()
+        .map(|b| utils::epoch_to_relative_str(b.timestamp.max(0) as u64))
+        .collect();
+    let max_name_len = branches
+        .iter()
+        .map(|b| b.name.chars().count())
+        .max()
+        .unwrap();
+    let max_age_len = ages.iter().map(|a| a.len()).max().unwrap();
+    let max_ahead = branches.iter().map(|b| b.ahead).max().unwrap();
+    let ahead_width =
+        utils::count_digits(max_ahead) + 1 + branches.iter().any(|b| b.ahead_capped) as usize;
+    // when piped, don't line things up
+    let (max_name_len, max_age_len, ahead_width) = if plain {
+        (0, 0, 0)
+    } else {
+        (max_name_len, max_age_len, ahead_width)
+    };
+
+    let green = utils::style(plain, Colour::Green);
+    let (green_prefix, green_suffix) = (green.prefix(), green.suffix());
+    for (branch, age) in branches.iter().zip(&ages) {
+        println!(
+            "  {:name_width$} {sep}{:>age_width$} {gp}{:>ahead_width$}{gs} {}",
+            branch.name,
+            age,
+            format!(
+                "+{}{}",
+                branch.ahead,
+                if branch.ahead_capped { "+" } else { "" }
+            ),
+            branch.summary,
+            name_width = max_name_len,
+            age_width = max_age_len,
+            ahead_width = ahead_width,
+            gp = green_prefix,
+            gs = green_suffix,
+            sep = if plain { "" } else { " " },
+        );
+    }
+
+    Ok(())
+}
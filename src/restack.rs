@@ -0,0 +1,186 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Rebasing stacks of branches after the branches they're built on changed, for `restack`.
+
+use crate::error::{Context, Error, Result};
+use std::collections::HashMap;
+use std::process::Command;
+
+/// A branch built on another one.
+struct Link {
+    child: String,
+    parent: String,
+    /// The tip of the parent the child was built on, which may since have been amended or rebased.
+    base: git2::Oid,
+}
+
+/// Get the tips `name` has had according to its reflog, newest first, starting with the current one.
+fn past_tips(repo: &git2::Repository, name: &str, tip: git2::Oid) -> Result<Vec<git2::Oid>> {
+    let mut tips = vec![tip];
+    let reflog = repo.reflog(&format!("refs/heads/{}", name))?;
+    for entry in reflog.iter() {
+        for oid in [entry.id_new(), entry.id_old()] {
+            if !oid.is_zero() && !tips.contains(&oid) {
+                tips.push(oid);
+            }
+        }
+    }
+    Ok(tips)
+}
+
+/// Find which branch each branch is built on, i.e. the branch with a tip (current or past) closest
+/// to it. Branches without commits of their own past the default branch don't count, since every
+/// branch builds on them.
+fn links(repo: &git2::Repository, default: git2::Oid) -> Result<Vec<Link>> {
+    let mut branches = Vec::new();
+    for maybe_branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = maybe_branch?;
+        let (name, tip) = match (branch.name()?, branch.get().target()) {
+            (Some(name), Some(tip)) => (name.to_string(), tip),
+            // rebasing goes through the command line, which we keep to UTF-8
+            _ => continue,
+        };
+        if tip == default || repo.graph_descendant_of(default, tip)? {
+            continue;
+        }
+        let tips = past_tips(repo, &name, tip)?;
+        branches.push((name, tip, tips));
+    }
+
+    let mut links = Vec::new();
+    for (child, child_tip, _) in &branches {
+        // (commits between the base and the child, whether the base is outdated) of the best so far
+        let mut best: Option<((usize, bool), Link)> = None;
+        for (parent, parent_tip, tips) in &branches {
+            if parent == child || repo.graph_descendant_of(*parent_tip, *child_tip)? {
+                continue;
+            }
+            for &base in tips {
+                if base == *child_tip
+                    || base == default
+                    || !repo.graph_descendant_of(*child_tip, base)?
+                    || repo.graph_descendant_of(default, base)?
+                {
+                    continue;
+                }
+                let (distance, _) = repo.graph_ahead_behind(*child_tip, base)?;
+                let key = (distance, base != *parent_tip);
+                if best.as_ref().is_none_or(|(k, _)| key < *k) {
+                    best = Some((
+                        key,
+                        Link {
+                            child: child.clone(),
+                            parent: parent.clone(),
+                            base,
+                        },
+                    ));
+                }
+            }
+        }
+        if let Some((_, link)) = best {
+            links.push(link);
+        }
+    }
+    Ok(links)
+}
+
+/// Order `links` so that parents are rebased before their children.
+fn order(mut links: Vec<Link>) -> Result<Vec<Link>> {
+    let parent_of: HashMap<&str, &str> = links
+        .iter()
+        .map(|l| (l.child.as_str(), l.parent.as_str()))
+        .collect();
+    let mut depths: HashMap<String, usize> = HashMap::new();
+    for link in &links {
+        let (mut name, mut depth) = (link.child.as_str(), 0);
+        while let Some(parent) = parent_of.get(name) {
+            depth += 1;
+            if depth > parent_of.len() {
+                return Err(Error::Command(format!(
+                    "branches built on each other in a loop, including {}; rebase one of them by \
+                     hand first",
+                    name
+                )));
+            }
+            name = parent;
+        }
+        depths.insert(link.child.clone(), depth);
+    }
+    links.sort_by_key(|l| depths[&l.child]);
+    Ok(links)
+}
+
+/// Run git in the working tree, failing with what it said.
+fn git(repo: &git2::Repository, args: &[&str]) -> Result<()> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| Error::NotFound("can't rebase in a bare repository".into()))?;
+    let output = Command::new("git")
+        .current_dir(workdir)
+        .args(args)
+        .output()
+        .map_err(|e| Error::Command(format!("running git: {}", e)))?;
+    if !output.status.success() {
+        // rebase reports conflicts on stdout and the rest on stderr
+        let said = [&output.stdout[..], &output.stderr[..]].concat();
+        let said = String::from_utf8_lossy(&said);
+        return Err(Error::Command(match said.trim() {
+            "" => format!("git {} failed", args.join(" ")),
+            said => said.to_string(),
+        }));
+    }
+    Ok(())
+}
+
+/// Rebase each branch of a stack whose parent moved onto the parent's new tip, parents first, or
+/// only print what would be rebased if `dry_run`. Stops at the first rebase that needs help.
+pub fn restack(repo: &git2::Repository, default: git2::Oid, dry_run: bool) -> Result<()> {
+    if let Some(state) = crate::describe_state(repo)? {
+        return Err(Error::Command(format!("{}; finish it first", state)));
+    }
+
+    let head = repo.head()?;
+    let head = match head.shorthand() {
+        Some(name) if head.is_branch() => name.to_string(),
+        _ => format!("{}", head.peel_to_commit()?.id()),
+    };
+
+    let mut rebased = 0;
+    for link in order(links(repo, default)?)? {
+        // look again, since the parent may just have been rebased itself
+        let parent = repo.find_branch(&link.parent, git2::BranchType::Local)?;
+        if parent.get().target() == Some(link.base) {
+            continue;
+        }
+        println!(
+            "{} {} onto {}",
+            if dry_run { "would rebase" } else { "rebasing" },
+            link.child,
+            link.parent
+        );
+        rebased += 1;
+        if dry_run {
+            continue;
+        }
+        let (onto, base) = (format!("refs/heads/{}", link.parent), link.base.to_string());
+        let args = ["rebase", "--quiet", "--onto", &onto, &base, &link.child];
+        if let Err(e) = git(repo, &args) {
+            return Err(Error::Command(format!(
+                "rebasing {} onto {} stopped:\n{}\nOnce it's sorted out with `git rebase \
+                 --continue` (or `--abort`), run `git bstatus restack` again.",
+                link.child, link.parent, e
+            )));
+        }
+    }
+
+    if rebased == 0 {
+        println!("Every stack is up to date.");
+    } else if !dry_run {
+        // rebasing checks out each branch in turn
+        git(repo, &["checkout", "--quiet", &head])
+            .with_context(|| format!("checking out {} again", head))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Installing a `git bs` alias, shell completions and a prompt snippet, for `setup`.
+
+use crate::error::{Context, Error, Result};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// For bash, git's own completion looks for `_git_bstatus` when completing `git bstatus`, loading
+/// it from the file named after `git-bstatus` if needed.
+const BASH_GIT_SHIM: &str = r#"
+_git_bstatus() {
+    COMP_WORDS=(git-bstatus "${COMP_WORDS[@]:2}")
+    COMP_CWORD=$((COMP_CWORD - 1))
+    _git-bstatus git-bstatus
+}
+"#;
+
+const PROMPT: &str = r#"# How many local branches aren't merged yet, e.g. ' (3 unmerged)', for the prompt.
+__bstatus_ps1() {
+    local n
+    n=$(git bstatus -u -n 2>/dev/null | wc -l)
+    [ "$n" -gt 0 ] && printf ' (%d unmerged)' "$n"
+}
+"#;
+
+/// Ask whether to go ahead with `what`, unless `yes`. Without a terminal to ask on, the answer is
+/// no.
+fn confirm(what: &str, yes: bool) -> Result<bool> {
+    if yes {
+        println!("{}", what);
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        println!("{} (skipped; run with --yes to do it anyway)", what);
+        return Ok(false);
+    }
+    eprint!("{}? [y/N] ", what);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| std::fs::write(path, contents))
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+/// Get `$<var>`, or `~/<fallback>` if it's not set.
+fn xdg_dir(var: &str, home: &Path, fallback: &str) -> PathBuf {
+    match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => dir.into(),
+        _ => home.join(fallback),
+    }
+}
+
+fn alias(yes: bool) -> Result<()> {
+    match git2::Config::open_default()?.get_string("alias.bs") {
+        Ok(ref cmd) if cmd == "bstatus" => {
+            println!("git bs is already set up.");
+            return Ok(());
+        }
+        Ok(cmd) => {
+            println!(
+                "alias.bs is already taken (by '{}'), leaving it alone.",
+                cmd
+            );
+            return Ok(());
+        }
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    if !confirm(
+        "Add git bs as a shortcut (git config --global alias.bs bstatus)",
+        yes,
+    )? {
+        return Ok(());
+    }
+    let status = Command::new("git")
+        .args(["config", "--global", "alias.bs", "bstatus"])
+        .status()
+        .map_err(|e| Error::Command(format!("running git: {}", e)))?;
+    if !status.success() {
+        return Err(Error::Command("git config --global failed".into()));
+    }
+    Ok(())
+}
+
+fn completions(app: &mut clap::App, shell: &str, home: &Path, yes: bool) -> Result<()> {
+    let (path, kind) = match shell {
+        "bash" => (
+            xdg_dir("XDG_DATA_HOME", home, ".local/share")
+                .join("bash-completion/completions/git-bstatus"),
+            clap::Shell::Bash,
+        ),
+        "zsh" => (home.join(".zfunc/_git-bstatus"), clap::Shell::Zsh),
+        _ => (
+            xdg_dir("XDG_CONFIG_HOME", home, ".config").join("fish/completions/git-bstatus.fish"),
+            clap::Shell::Fish,
+        ),
+    };
+    if !confirm(
+        &format!("Install {} completions to {}", shell, path.display()),
+        yes,
+    )? {
+        return Ok(());
+    }
+    let mut script = Vec::new();
+    app.gen_completions_to("git-bstatus", kind, &mut script);
+    if shell == "bash" {
+        script.extend_from_slice(BASH_GIT_SHIM.as_bytes());
+    }
+    write(&path, &script)?;
+    if shell == "zsh" {
+        println!("  (make sure ~/.zfunc is in fpath before compinit in ~/.zshrc)");
+    }
+    Ok(())
+}
+
+fn prompt(shell: &str, home: &Path, yes: bool) -> Result<()> {
+    if shell == "fish" {
+        println!("There's no prompt snippet for fish yet.");
+        return Ok(());
+    }
+    let path = xdg_dir("XDG_CONFIG_HOME", home, ".config").join("git-bstatus/prompt.sh");
+    if !confirm(
+        &format!("Install the prompt snippet to {}", path.display()),
+        yes,
+    )? {
+        print!("\n{}", PROMPT);
+        return Ok(());
+    }
+    write(&path, PROMPT.as_bytes())?;
+    println!(
+        "\nTo show how many branches aren't merged yet in your prompt, add to ~/.{}rc:",
+        shell
+    );
+    println!("  source {}", path.display());
+    if shell == "zsh" {
+        println!("  setopt PROMPT_SUBST");
+        println!("  PROMPT='%~$(__bstatus_ps1)%# '");
+    } else {
+        println!("  PS1='\\w$(__bstatus_ps1)\\$ '");
+    }
+    Ok(())
+}
+
+/// Walk through setting up `git bs`, completions for `shell` (or the one in `$SHELL`) and the
+/// prompt snippet, asking before each step unless `yes`.
+pub fn setup(mut app: clap::App, shell: Option<&str>, yes: bool) -> Result<()> {
+    let home: PathBuf = std::env::var_os("HOME")
+        .ok_or_else(|| Error::NotFound("can't tell where your home directory is".into()))?
+        .into();
+    let shell = match shell {
+        Some(shell) => shell.to_string(),
+        None => {
+            let path = std::env::var("SHELL").unwrap_or_default();
+            path.rsplit('/').next().unwrap_or("").to_string()
+        }
+    };
+    if !["bash", "zsh", "fish"].contains(&shell.as_str()) {
+        return Err(Error::NotFound(format!(
+            "don't know how to set up shell '{}' (pass --shell bash, zsh or fish?)",
+            shell
+        )));
+    }
+
+    alias(yes)?;
+    completions(&mut app, &shell, &home, yes)?;
+    prompt(&shell, &home, yes)
+}
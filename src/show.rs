@@ -0,0 +1,195 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::compare;
+use crate::error::{Context, Error, Result};
+use crate::utils;
+use ansi_term::Colour;
+
+fn ago(timestamp: i64) -> String {
+    match utils::epoch_to_relative_str(timestamp.max(0) as u64).as_str() {
+        "now" => "just now".into(),
+        age => format!("{} ago", age),
+    }
+}
+
+/// Describe how `oid` compares to `other`, e.g. "↑2 ↓1" or "up to date".
+fn divergence(repo: &git2::Repository, oid: git2::Oid, other: git2::Oid) -> Result<String> {
+    let (ahead, behind) = repo.graph_ahead_behind(oid, other)?;
+    Ok(match utils::divergence_str(ahead, behind) {
+        s if s.is_empty() => "up to date".into(),
+        s => s,
+    })
+}
+
+fn config_string(config: &git2::Config, key: &str) -> Result<Option<String>> {
+    match config.get_string(key) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Find where `git push` would send `name`, the way `@{push}` resolves it for the default
+/// `push.default` modes: the branch of the same name on its push remote. Returns the
+/// remote-tracking branch and its tip, if it's been fetched.
+fn push_target(repo: &git2::Repository, name: &str) -> Result<Option<(String, Option<git2::Oid>)>> {
+    let config = repo.config()?;
+    let remote = match config_string(&config, &format!("branch.{}.pushRemote", name))? {
+        Some(remote) => remote,
+        None => match config_string(&config, "remote.pushDefault")? {
+            Some(remote) => remote,
+            None => match config_string(&config, &format!("branch.{}.remote", name))? {
+                Some(remote) => remote,
+                None => return Ok(None),
+            },
+        },
+    };
+    // pushing to the local repo itself isn't interesting
+    if remote == "." {
+        return Ok(None);
+    }
+    let shorthand = format!("{}/{}", remote, name);
+    match repo.refname_to_id(&format!("refs/remotes/{}", shorthand)) {
+        Ok(oid) => Ok(Some((shorthand, Some(oid)))),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(Some((shorthand, None))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get the files which would conflict if `oid` were merged into `base`, from an in-memory merge.
+fn conflicts(repo: &git2::Repository, oid: git2::Oid, base: git2::Oid) -> Result<Vec<String>> {
+    let index = repo.merge_commits(&repo.find_commit(base)?, &repo.find_commit(oid)?, None)?;
+    let mut paths = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            paths.push(utils::escape_bytes(&entry.path));
+        }
+    }
+    Ok(paths)
+}
+
+/// Print everything we know about local branch `name` compared to `base` (the default branch,
+/// called `base_name`), as a card.
+pub fn show(
+    repo: &git2::Repository,
+    name: &str,
+    base: git2::Oid,
+    base_name: &str,
+    max_ahead: Option<usize>,
+    plain: bool,
+) -> Result<()> {
+    let branch = match repo.find_branch(name, git2::BranchType::Local) {
+        Ok(branch) => branch,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            return Err(Error::NotFound(format!("branch {} not found", name)))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let commit = branch.get().peel_to_commit()?;
+    let oid = commit.id();
+    let bold = utils::style(plain, ansi_term::Style::new().bold());
+    let green = utils::style(plain, Colour::Green);
+
+    print!("{}", bold.paint(name));
+    if branch.is_head() {
+        print!(" (checked out)");
+    }
+    println!();
+
+    match branch.upstream() {
+        Ok(upstream) => {
+            let upstream_oid = upstream.get().peel_to_commit()?.id();
+            println!(
+                "Upstream:     {} ({})",
+                utils::escape_bytes(upstream.name_bytes()?),
+                divergence(repo, oid, upstream_oid)?
+            );
+        }
+        Err(e) if e.code() == git2::ErrorCode::NotFound => println!("Upstream:     none"),
+        Err(e) => return Err(e.into()),
+    }
+
+    match push_target(repo, name).with_context(|| format!("finding where {} pushes to", name))? {
+        Some((target, Some(target_oid))) => println!(
+            "Push target:  {} ({})",
+            target,
+            divergence(repo, oid, target_oid)?
+        ),
+        Some((target, None)) => println!("Push target:  {} (not pushed yet)", target),
+        None => println!("Push target:  none"),
+    }
+
+    println!(
+        "Base:         {} ({})",
+        base_name,
+        divergence(repo, oid, base)?
+    );
+    match repo.merge_base(oid, base) {
+        Ok(merge_base) => {
+            let merge_base = repo.find_commit(merge_base)?;
+            println!(
+                "Merge base:   {:.8} {} ({})",
+                merge_base.id(),
+                utils::commit_summary(&merge_base),
+                ago(merge_base.time().seconds())
+            );
+        }
+        Err(e) if e.code() == git2::ErrorCode::NotFound => println!("Merge base:   none"),
+        Err(_) if repo.is_shallow() => println!("Merge base:   not in shallow clone"),
+        Err(e) => return Err(e.into()),
+    }
+    println!("Last commit:  {}", ago(commit.time().seconds()));
+
+    if let Some(description) =
+        config_string(&repo.config()?, &format!("branch.{}.description", name))?
+    {
+        let mut lines = description.trim_end().lines();
+        println!("Description:  {}", lines.next().unwrap_or_default());
+        for line in lines {
+            println!("              {}", line);
+        }
+    }
+
+    match conflicts(repo, oid, base) {
+        Ok(paths) if paths.is_empty() => println!("Conflicts:    none with {}", base_name),
+        Ok(paths) => {
+            println!(
+                "Conflicts:    {} with {}:",
+                utils::style(plain, Colour::Red).paint(utils::plural("file", paths.len() as u64)),
+                base_name
+            );
+            for path in &paths {
+                println!("                {}", path);
+            }
+        }
+        // e.g. no merge base, or objects missing from a partial or shallow clone
+        Err(e) => println!("Conflicts:    unknown ({})", e),
+    }
+
+    let (ahead, capped) = compare::only_in(repo, oid, base, max_ahead)
+        .with_context(|| format!("listing commits on {}", name))?;
+    if !ahead.is_empty() {
+        let count = utils::plural("commit", ahead.len() as u64);
+        println!(
+            "\n{} not in {}:",
+            green.paint(if capped {
+                format!("at least {}", count)
+            } else {
+                count
+            }),
+            base_name
+        );
+        for oid in ahead {
+            let commit = repo.find_commit(oid)?;
+            println!("    {:.8} {}", oid, utils::commit_summary(&commit));
+        }
+        if capped {
+            println!("    ...");
+        }
+    }
+
+    Ok(())
+}
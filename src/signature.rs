@@ -0,0 +1,71 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::error::{Error, Result};
+use std::process::Command;
+
+/// Verify the signature of `oid`, or if `base` is given, of all the commits reachable from `oid`
+/// but not from `base` (up to `max`), and return the worst result as a single character: `✔` for
+/// good signatures, `✘` for bad or revoked ones, `-` for unsigned commits and `?` for signatures
+/// which can't be fully checked (e.g. unknown or expired keys).
+///
+/// libgit2 can extract signatures but not check them, so this goes through `git`, which knows
+/// about the user's GPG and SSH setup.
+pub fn verify(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    base: Option<git2::Oid>,
+    max: Option<usize>,
+) -> Result<char> {
+    let mut cmd = Command::new("git");
+    cmd.arg("--git-dir")
+        .arg(repo.path())
+        .arg("log")
+        .arg("--format=%G?")
+        .arg(oid.to_string());
+    match base {
+        Some(base) => {
+            cmd.arg(format!("^{}", base));
+            if let Some(max) = max {
+                cmd.arg(format!("--max-count={}", max));
+            }
+        }
+        None => {
+            cmd.arg("--max-count=1");
+        }
+    }
+
+    let out = cmd.output()?;
+    if !out.status.success() {
+        return Err(Error::Command(format!(
+            "git log failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+
+    let worst = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|status| match status {
+            "G" => '✔',
+            "B" | "R" => '✘',
+            "N" => '-',
+            _ => '?',
+        })
+        .max_by_key(|&c| rank(c));
+    // nothing to check if the branch has no commits of its own
+    match worst {
+        Some(c) => Ok(c),
+        None if base.is_some() => verify(repo, oid, None, None),
+        None => Ok('?'),
+    }
+}
+
+fn rank(c: char) -> u8 {
+    match c {
+        '✔' => 0,
+        '?' => 1,
+        '-' => 2,
+        _ => 3,
+    }
+}
@@ -0,0 +1,160 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! How much a branch adds to the repository, for `--sizes`.
+
+use crate::compare;
+use crate::error::Result;
+use std::collections::HashSet;
+
+/// The objects a branch adds, i.e. those its commits changed compared to their parents, much like
+/// `git rev-list --objects base..branch`. Only the same path in the parents is looked at, so
+/// content which was already elsewhere (e.g. a moved file) counts as added.
+#[derive(Clone, Copy, Default)]
+pub struct Size {
+    pub objects: usize,
+    /// Their total size, uncompressed, so it doesn't depend on how things were packed.
+    pub bytes: u64,
+    /// Whether this is only a lower bound: we stopped looking at `--max-ahead` commits or at the
+    /// edge of a shallow clone, or some objects were left out of a partial clone.
+    pub capped: bool,
+}
+
+/// The objects found so far.
+struct Walk<'r> {
+    repo: &'r git2::Repository,
+    seen: HashSet<git2::Oid>,
+    new: Vec<git2::Oid>,
+    /// Whether some of the trees weren't there to look at.
+    missing: bool,
+}
+
+impl<'r> Walk<'r> {
+    /// Get `oid` as a tree, if it's been fetched.
+    fn tree(&mut self, oid: git2::Oid) -> Result<Option<git2::Tree<'r>>> {
+        match self.repo.find_tree(oid) {
+            Ok(tree) => Ok(Some(tree)),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                self.missing = true;
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Push what's in `tree` but not at the same place in any of the `old` trees (those of the
+    /// parents) onto `new`. Identical subtrees are skipped without looking inside.
+    fn added(&mut self, tree: git2::Oid, old: &[git2::Oid]) -> Result<()> {
+        if old.contains(&tree) || !self.seen.insert(tree) {
+            return Ok(());
+        }
+        self.new.push(tree);
+        let tree = match self.tree(tree)? {
+            Some(tree) => tree,
+            None => return Ok(()),
+        };
+        let mut old_trees = Vec::new();
+        for &oid in old {
+            old_trees.extend(self.tree(oid)?);
+        }
+        for entry in tree.iter() {
+            let before: Vec<(git2::Oid, Option<git2::ObjectType>)> = old_trees
+                .iter()
+                .filter_map(|t| t.get_name_bytes(entry.name_bytes()))
+                .map(|e| (e.id(), e.kind()))
+                .collect();
+            if before.iter().any(|(id, _)| *id == entry.id()) {
+                continue;
+            }
+            match entry.kind() {
+                Some(git2::ObjectType::Tree) => {
+                    let old: Vec<git2::Oid> = before
+                        .iter()
+                        .filter(|(_, kind)| *kind == Some(git2::ObjectType::Tree))
+                        .map(|(id, _)| *id)
+                        .collect();
+                    self.added(entry.id(), &old)?;
+                }
+                Some(git2::ObjectType::Blob) if self.seen.insert(entry.id()) => {
+                    self.new.push(entry.id())
+                }
+                // seen blobs, and submodules, which live in their own repos
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Count the objects the commits on `oid` which aren't on `base` add, stopping after `max`
+/// commits.
+pub fn new_objects(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    base: git2::Oid,
+    max: Option<usize>,
+) -> Result<Size> {
+    let (commits, capped) = compare::only_in(repo, oid, base, max)?;
+
+    // each commit adds what it changed compared to its parents; what it has in common with a
+    // parent on the branch too is counted there instead
+    let mut walk = Walk {
+        repo,
+        seen: HashSet::new(),
+        new: commits.clone(),
+        missing: false,
+    };
+    for c in &commits {
+        let commit = repo.find_commit(*c)?;
+        let mut old = Vec::new();
+        for parent in commit.parent_ids() {
+            match repo.find_commit(parent) {
+                Ok(parent) => old.push(parent.tree_id()),
+                Err(e) if e.code() == git2::ErrorCode::NotFound && repo.is_shallow() => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        walk.added(commit.tree_id(), &old)?;
+    }
+
+    let odb = repo.odb()?;
+    let mut bytes = 0;
+    for oid in &walk.new {
+        match odb.read_header(*oid) {
+            Ok((size, _)) => bytes += size as u64,
+            // not fetched into a partial clone, so it's not counted, but it's still there
+            Err(e) if e.code() == git2::ErrorCode::NotFound => walk.missing = true,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Size {
+        objects: walk.new.len(),
+        bytes,
+        capped: capped || walk.missing,
+    })
+}
+
+/// Format a size in bytes for people, e.g. `3.4 MiB`.
+pub fn human(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[test]
+fn test_human() {
+    assert_eq!("0 B", human(0));
+    assert_eq!("1023 B", human(1023));
+    assert_eq!("1.0 KiB", human(1024));
+    assert_eq!("3.4 MiB", human(3_565_158));
+    assert_eq!("2048.0 TiB", human(1 << 51));
+}
@@ -0,0 +1,65 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+use crate::error::{Error, Result};
+use crate::utils;
+use ansi_term::Colour;
+
+/// Check whether checking out `name` would succeed, printing what would block it otherwise.
+pub fn can_switch(repo: &git2::Repository, name: &str, plain: bool) -> Result<bool> {
+    let blocked = utils::style(plain, Colour::Red).paint("blocked:");
+    let branch = repo.find_branch(name, git2::BranchType::Local)?;
+    let tree = match branch.get().peel_to_tree() {
+        Ok(tree) => tree,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            return Err(Error::NotLocal(format!(
+                "the files of {} haven't been fetched (partial clone?), so there's no telling; \
+                 fetch them first",
+                name
+            )))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut ok = true;
+    if let Some(state) = crate::describe_state(repo)? {
+        println!("{} {}", blocked, state);
+        ok = false;
+    }
+
+    // do a dry run of a safe checkout and collect anything it would refuse to overwrite
+    let mut conflicts: Vec<String> = Vec::new();
+    {
+        let mut builder = git2::build::CheckoutBuilder::new();
+        builder
+            .dry_run()
+            .notify_on(git2::CheckoutNotificationType::CONFLICT)
+            .notify(|_, path, _, _, _| {
+                if let Some(p) = path {
+                    conflicts.push(p.to_string_lossy().into());
+                }
+                true
+            });
+        // a conflict makes the dry run itself fail; the callback already recorded the paths
+        if let Err(e) = repo.checkout_tree(tree.as_object(), Some(&mut builder)) {
+            if e.code() != git2::ErrorCode::Conflict {
+                return Err(e.into());
+            }
+        }
+    }
+
+    if !conflicts.is_empty() {
+        println!("{} local changes would be overwritten:", blocked);
+        for path in &conflicts {
+            println!("    {}", path);
+        }
+        ok = false;
+    }
+
+    if ok {
+        println!("Branch {} can be checked out.", name);
+    }
+
+    Ok(ok)
+}
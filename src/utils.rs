@@ -2,6 +2,9 @@
  * SPDX-License-Identifier: MIT
  * */
 
+use ansi_term::Style;
+use std::cmp::Ordering;
+use std::io::IsTerminal;
 use std::time;
 
 const SECONDS_PER_MINUTE: u64 = 60;
@@ -10,9 +13,9 @@ const HOURS_PER_DAY: u64 = 24;
 const DAYS_PER_WEEK: u64 = 7;
 const DAYS_PER_MONTH: u64 = 30; // meh... good enough for our purposes
 const MONTHS_PER_YEAR: u64 = 12;
+const SECONDS_PER_DAY: u64 = SECONDS_PER_MINUTE * MINUTES_PER_HOUR * HOURS_PER_DAY;
 
 pub fn epoch_to_relative_str(timestamp: u64) -> String {
-    let timestamp = timestamp as u64;
     let now = time::SystemTime::now()
         .duration_since(time::UNIX_EPOCH)
         .unwrap()
@@ -54,10 +57,92 @@ pub fn epoch_to_relative_str(timestamp: u64) -> String {
     plural("year", years)
 }
 
-fn plural(s: &str, n: u64) -> String {
+/// Parse a duration like `30d`, `2 weeks` or `6months` into seconds. Months are 30 days, like in
+/// `epoch_to_relative_str()`; `m` alone is rejected since it could mean minutes or months.
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n: u64 = s[..split].parse().ok()?;
+    let secs = match s[split..].trim_start() {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "min" | "mins" | "minute" | "minutes" => SECONDS_PER_MINUTE,
+        "h" | "hour" | "hours" => SECONDS_PER_MINUTE * MINUTES_PER_HOUR,
+        "d" | "day" | "days" => SECONDS_PER_DAY,
+        "w" | "week" | "weeks" => SECONDS_PER_DAY * DAYS_PER_WEEK,
+        "month" | "months" => SECONDS_PER_DAY * DAYS_PER_MONTH,
+        "y" | "year" | "years" => SECONDS_PER_DAY * DAYS_PER_MONTH * MONTHS_PER_YEAR,
+        _ => return None,
+    };
+    n.checked_mul(secs)
+}
+
+pub fn plural(s: &str, n: u64) -> String {
     format!("{} {}{}", n, s, if n == 1 { "" } else { "s" })
 }
 
+/// Format commits ahead and behind as e.g. `↑2 ↓1`, leaving out zero counts.
+pub fn divergence_str(ahead: usize, behind: usize) -> String {
+    let mut parts = Vec::new();
+    if ahead > 0 {
+        parts.push(format!("↑{}", ahead));
+    }
+    if behind > 0 {
+        parts.push(format!("↓{}", behind));
+    }
+    parts.join(" ")
+}
+
+/// Get the width of the terminal, from `COLUMNS` if it's set, or else from stdout if it's a
+/// terminal. Falls back to 80.
+pub fn term_width() -> usize {
+    if let Some(width) = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        return width;
+    }
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+        {
+            return size.ws_col as usize;
+        }
+    }
+    80
+}
+
+/// Lay out items with the given `widths` in columns filled top to bottom, using as few rows as
+/// fit in `width` with `gap` spaces between columns. Returns the number of rows and the width of
+/// each column.
+pub fn column_layout(widths: &[usize], width: usize, gap: usize) -> (usize, Vec<usize>) {
+    let mut rows = 1;
+    loop {
+        let columns: Vec<usize> = widths
+            .chunks(rows)
+            .map(|c| c.iter().copied().max().unwrap_or(0))
+            .collect();
+        let total = columns.iter().sum::<usize>() + gap * columns.len().saturating_sub(1);
+        if total <= width || rows >= widths.len() {
+            return (if widths.is_empty() { 0 } else { rows }, columns);
+        }
+        rows += 1;
+    }
+}
+
+/// Whether `stream` is a terminal we can send colours to, i.e. not a pipe or a file, and not one
+/// with `TERM=dumb`.
+pub fn is_fancy<T: IsTerminal>(stream: T) -> bool {
+    stream.is_terminal() && std::env::var_os("TERM") != Some("dumb".into())
+}
+
+/// `style`, or no styling at all if `plain`.
+pub fn style(plain: bool, style: impl Into<Style>) -> Style {
+    if plain {
+        Style::default()
+    } else {
+        style.into()
+    }
+}
+
 pub fn count_digits(mut n: usize) -> usize {
     match n {
         0..=9 => 1,
@@ -73,6 +158,182 @@ pub fn count_digits(mut n: usize) -> usize {
     }
 }
 
+/// Get the summary of a commit, even if it's empty or not valid UTF-8.
+pub fn commit_summary(commit: &git2::Commit) -> String {
+    match commit.summary_bytes() {
+        Some(s) => String::from_utf8_lossy(s).into(),
+        None => String::new(),
+    }
+}
+
+/// Make arbitrary bytes (e.g. a ref name) printable: invalid UTF-8 and control characters are
+/// escaped as `\xNN`.
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() {
+                s.push_str(&format!("\\x{:02x}", c as u32));
+            } else {
+                s.push(c);
+            }
+        }
+        for b in chunk.invalid() {
+            s.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    s
+}
+
+/// Quote `bytes` for a POSIX shell, leaving them as is if there's nothing to quote.
+pub fn shell_quote(bytes: &[u8]) -> Vec<u8> {
+    let safe = |&b: &u8| b.is_ascii_alphanumeric() || b"/._-+@,:%".contains(&b);
+    if !bytes.is_empty() && bytes.iter().all(safe) {
+        return bytes.to_vec();
+    }
+    let mut quoted = vec![b'\''];
+    for &b in bytes {
+        if b == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(b);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// Compare two strings the way `sort -V` would, i.e. with runs of digits compared numerically.
+pub fn version_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_len = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+                let b_len = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+                let (a_num, b_num) = (
+                    a[..a_len].trim_start_matches('0'),
+                    b[..b_len].trim_start_matches('0'),
+                );
+                let o = a_num.len().cmp(&b_num.len()).then(a_num.cmp(b_num));
+                if o != Ordering::Equal {
+                    return o;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// A row of a branch name tree as rendered by `tree_rows()`.
+#[derive(Debug, PartialEq)]
+pub enum TreeRow {
+    /// A shared prefix, e.g. `feature/`.
+    Dir { depth: usize, label: String },
+    /// A branch; `index` points back into the slice of names passed in.
+    Leaf {
+        depth: usize,
+        label: String,
+        index: usize,
+    },
+}
+
+#[derive(Default)]
+struct TreeNode {
+    component: String,
+    index: Option<usize>,
+    children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    fn n_leaves(&self) -> usize {
+        self.index.iter().count() + self.children.iter().map(|c| c.n_leaves()).sum::<usize>()
+    }
+
+    fn emit(&self, depth: usize, prefix: String, rows: &mut Vec<TreeRow>) {
+        let label = format!("{}{}", prefix, self.component);
+        if let Some(index) = self.index {
+            rows.push(TreeRow::Leaf {
+                depth,
+                label,
+                index,
+            });
+        } else if self.n_leaves() == 1 || self.children.len() == 1 {
+            // collapse chains which don't branch out, e.g. `fix/bar` or `user/feature/`
+            self.children[0].emit(depth, format!("{}/", label), rows);
+        } else {
+            rows.push(TreeRow::Dir {
+                depth,
+                label: format!("{}/", label),
+            });
+            for child in &self.children {
+                child.emit(depth + 1, String::new(), rows);
+            }
+        }
+    }
+}
+
+/// Lay out slash-separated names as a tree, sharing common prefixes. Siblings keep the order in
+/// which they first appear in `names`.
+pub fn tree_rows(names: &[&str]) -> Vec<TreeRow> {
+    let mut root = TreeNode::default();
+    for (index, name) in names.iter().enumerate() {
+        let mut node = &mut root;
+        for component in name.split('/') {
+            let pos = match node.children.iter().position(|c| c.component == component) {
+                Some(pos) => pos,
+                None => {
+                    node.children.push(TreeNode {
+                        component: component.into(),
+                        ..Default::default()
+                    });
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[pos];
+        }
+        node.index = Some(index);
+    }
+
+    let mut rows = Vec::new();
+    for child in &root.children {
+        child.emit(0, String::new(), &mut rows);
+    }
+    rows
+}
+
+/// Given the parent (if any) of each node, return `(depth, index)` pairs in depth-first order, with
+/// roots and siblings kept in their original order.
+pub fn forest_order(parents: &[Option<usize>]) -> Vec<(usize, usize)> {
+    fn visit(parents: &[Option<usize>], i: usize, depth: usize, out: &mut Vec<(usize, usize)>) {
+        out.push((depth, i));
+        for (j, p) in parents.iter().enumerate() {
+            if *p == Some(i) {
+                visit(parents, j, depth + 1, out);
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for (i, p) in parents.iter().enumerate() {
+        if p.is_none() {
+            visit(parents, i, 0, &mut out);
+        }
+    }
+    out
+}
+
 #[test]
 fn test_count_digits() {
     assert_eq!(1, count_digits(0));
@@ -87,3 +348,73 @@ fn test_count_digits() {
     assert_eq!(4, count_digits(1000));
     assert_eq!(4, count_digits(1001));
 }
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(Some(30 * 86400), parse_duration("30d"));
+    assert_eq!(Some(2 * 7 * 86400), parse_duration("2 weeks"));
+    assert_eq!(Some(6 * 30 * 86400), parse_duration("6months"));
+    assert_eq!(Some(90), parse_duration("90s"));
+    assert_eq!(None, parse_duration("3m"));
+    assert_eq!(None, parse_duration("d"));
+    assert_eq!(None, parse_duration("5 fortnights"));
+}
+
+#[test]
+fn test_version_cmp() {
+    assert_eq!(Ordering::Less, version_cmp("v1.2", "v1.10"));
+    assert_eq!(Ordering::Greater, version_cmp("v2.0", "v1.99.1"));
+    assert_eq!(Ordering::Less, version_cmp("v1.0", "v1.0.1"));
+    assert_eq!(Ordering::Equal, version_cmp("v1.01", "v1.1"));
+    assert_eq!(Ordering::Less, version_cmp("1.0", "v1.0"));
+}
+
+#[test]
+fn test_shell_quote() {
+    assert_eq!(b"feature/foo".to_vec(), shell_quote(b"feature/foo"));
+    assert_eq!(b"''".to_vec(), shell_quote(b""));
+    assert_eq!(b"'a b$'".to_vec(), shell_quote(b"a b$"));
+    assert_eq!(b"'it'\\''s'".to_vec(), shell_quote(b"it's"));
+    assert_eq!(b"'caf\xe9'".to_vec(), shell_quote(b"caf\xe9"));
+}
+
+#[test]
+fn test_column_layout() {
+    assert_eq!((0, vec![]), column_layout(&[], 80, 2));
+    assert_eq!((1, vec![3, 1, 2]), column_layout(&[3, 1, 2], 80, 2));
+    assert_eq!((2, vec![5, 4]), column_layout(&[5, 1, 4, 2], 12, 2));
+    assert_eq!((3, vec![20]), column_layout(&[20, 3, 4], 10, 2));
+}
+
+#[test]
+fn test_tree_rows() {
+    let leaf = |depth, label: &str, index| TreeRow::Leaf {
+        depth,
+        label: label.into(),
+        index,
+    };
+    let dir = |depth, label: &str| TreeRow::Dir {
+        depth,
+        label: label.into(),
+    };
+    assert_eq!(
+        tree_rows(&[
+            "feature/login",
+            "master",
+            "fix/bar",
+            "feature/signup",
+            "me/wip/a",
+            "me/wip/b"
+        ]),
+        vec![
+            dir(0, "feature/"),
+            leaf(1, "login", 0),
+            leaf(1, "signup", 3),
+            leaf(0, "master", 1),
+            leaf(0, "fix/bar", 2),
+            dir(0, "me/wip/"),
+            leaf(1, "a", 4),
+            leaf(1, "b", 5),
+        ]
+    );
+}
@@ -0,0 +1,119 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `--author` and `--mine` list only the branches someone worked on, going by `.mailmap`.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+use std::path::{Path, PathBuf};
+
+/// Commit on the current branch as `who` (e.g. `Alice <alice@example.com>`), with `committer`
+/// as the committer's name and email.
+fn commit_as(repo: &Path, who: &str, committer: (&str, &str), msg: &str) {
+    let name = format!("user.name={}", committer.0);
+    let email = format!("user.email={}", committer.1);
+    git(
+        repo,
+        &[
+            "-c",
+            &name,
+            "-c",
+            &email,
+            "commit",
+            "-q",
+            "--allow-empty",
+            "--author",
+            who,
+            "-m",
+            msg,
+        ],
+    );
+}
+
+/// Create branches worked on by different people, with Alice's old address mapped to her new
+/// one.
+fn people(dir: &Path) -> PathBuf {
+    let repo = init_repo(dir, "repo");
+    let alice = ("Alice", "alice@example.com");
+    let bob = ("Bob", "bob@example.com");
+
+    git(&repo, &["checkout", "-qb", "alice-tip"]);
+    commit_as(&repo, "Alice <alice@example.com>", alice, "alice");
+    git(&repo, &["checkout", "-qb", "bob-tip", "master"]);
+    commit_as(&repo, "Alice <alice@example.com>", alice, "alice first");
+    commit_as(&repo, "Bob <bob@example.com>", bob, "bob after");
+    git(&repo, &["checkout", "-qb", "bob-only", "master"]);
+    commit_as(&repo, "Bob <bob@example.com>", bob, "bob");
+    git(&repo, &["checkout", "-qb", "applied", "master"]);
+    commit_as(&repo, "Bob <bob@example.com>", alice, "applied by alice");
+    git(&repo, &["checkout", "-qb", "old-address", "master"]);
+    commit_as(
+        &repo,
+        "Al <al@old.example>",
+        ("Al", "al@old.example"),
+        "old",
+    );
+    git(&repo, &["checkout", "-q", "master"]);
+
+    std::fs::write(
+        repo.join(".mailmap"),
+        "Alice <alice@example.com> <al@old.example>\n",
+    )
+    .unwrap();
+    repo
+}
+
+/// The branch names listed in `out`.
+fn names(out: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = out.lines().map(|l| l.trim()).collect();
+    names.sort_unstable();
+    names
+}
+
+#[test]
+fn author() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = people(dir.path());
+
+    // only the tip counts, whether authored or committed, under the mailmapped identity
+    let out = bstatus(&repo, &["-a", "-n", "--author", "Alice"]);
+    assert_eq!(
+        names(&out),
+        ["alice-tip", "applied", "old-address"],
+        "{}",
+        out
+    );
+    let out = bstatus(&repo, &["-a", "-n", "--author", "bob@example.com"]);
+    assert_eq!(names(&out), ["applied", "bob-only", "bob-tip"], "{}", out);
+    let out = bstatus(&repo, &["-a", "-n", "--author", "nobody"]);
+    assert_eq!(out, "");
+}
+
+#[test]
+fn mine() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = people(dir.path());
+
+    // any commit on the branch counts, and like git, emails match whatever their case
+    git(&repo, &["config", "user.name", "Alice"]);
+    git(&repo, &["config", "user.email", "Alice@Example.com"]);
+    let out = bstatus(&repo, &["-a", "-n", "--mine"]);
+    assert_eq!(
+        names(&out),
+        ["alice-tip", "applied", "bob-tip", "old-address"],
+        "{}",
+        out
+    );
+
+    // whoever used the old address is them as well
+    git(&repo, &["config", "user.email", "al@old.example"]);
+    let out = bstatus(&repo, &["-a", "-n", "--mine"]);
+    assert_eq!(
+        names(&out),
+        ["alice-tip", "applied", "bob-tip", "old-address"],
+        "{}",
+        out
+    );
+}
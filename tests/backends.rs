@@ -0,0 +1,38 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Every backend should see the same branches the same way.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn tag_named_like_branch() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    // old enough that the ages shown don't change between the two runs
+    let commit = |args: &[&str]| {
+        std::process::Command::new("git")
+            .current_dir(&repo)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .arg("commit")
+            .args(args)
+            .env("GIT_COMMITTER_DATE", "2001-01-01T00:00:00")
+            .status()
+            .unwrap()
+    };
+    commit(&["-q", "--amend", "--no-edit"]);
+    git(&repo, &["checkout", "-qb", "topic"]);
+    commit(&["-q", "--allow-empty", "-m", "topic"]);
+    git(&repo, &["branch", "-q", "--set-upstream-to", "master"]);
+    // makes `topic` ambiguous, which `refname:short` resolves with `heads/topic`
+    git(&repo, &["tag", "topic", "master"]);
+    git(&repo, &["tag", "master", "master"]);
+
+    let expected = bstatus(&repo, &["-a"]);
+    assert!(expected.contains(" topic "), "{}", expected);
+    assert!(expected.contains("(master ↑1)"), "{}", expected);
+    assert_eq!(expected, bstatus(&repo, &["-a", "--backend", "cli"]));
+}
@@ -0,0 +1,31 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `backup` pushes local-only branches to `refs/backups/<host>/`, once per tip.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn backup_twice() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(dir.path(), &["init", "-q", "--bare", "backups.git"]);
+    git(&repo, &["remote", "add", "backups", "../backups.git"]);
+    git(&repo, &["checkout", "-qb", "feature"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "feature"]);
+
+    let out = bstatus(&repo, &["backup", "--remote", "backups"]);
+    assert!(out.contains("pushing feature"), "{}", out);
+
+    let out = bstatus(&repo, &["backup", "--remote", "backups"]);
+    assert!(!out.contains("pushing"), "{}", out);
+    assert!(out.contains("up to date"), "{}", out);
+
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "more"]);
+    let out = bstatus(&repo, &["backup", "--remote", "backups"]);
+    assert!(out.contains("pushing feature"), "{}", out);
+    assert!(!out.contains("pushing master"), "{}", out);
+}
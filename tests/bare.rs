@@ -0,0 +1,30 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Bare repos, e.g. server-side mirrors, have branches but nothing checked out.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn bare_repo() {
+    let dir = tempfile::tempdir().unwrap();
+    let origin = init_repo(dir.path(), "origin");
+    git(&origin, &["checkout", "-qb", "feature"]);
+    git(&origin, &["commit", "-q", "--allow-empty", "-m", "feature"]);
+    git(
+        dir.path(),
+        &["clone", "-q", "--mirror", "origin", "bare.git"],
+    );
+    let bare = dir.path().join("bare.git");
+
+    let out = bstatus(&bare, &["--changes"]);
+    assert!(out.starts_with("Bare repository"), "{}", out);
+
+    let out = bstatus(&bare, &["-a", "--dirty"]);
+    assert!(out.contains("feature"), "{}", out);
+    assert!(out.contains("+1"), "{}", out);
+    assert!(!out.contains("[dirty]"), "{}", out);
+}
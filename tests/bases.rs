@@ -0,0 +1,65 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Comparing branches against the nearest of several long-lived bases.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn nearest_declared_base() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "develop"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "d1"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "d2"]);
+    git(&repo, &["checkout", "-qb", "feature"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "f"]);
+    git(&repo, &["checkout", "-qb", "hotfix", "master"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "h"]);
+    git(&repo, &["config", "bstatus.bases", "master, develop"]);
+
+    let out: serde_json::Value = serde_json::from_str(&bstatus(&repo, &["--json"])).unwrap();
+    let branch = |name: &str| {
+        out["branches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|b| b["name"] == name)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(branch("feature")["ahead"], 1);
+    assert_eq!(branch("feature")["base"], "develop");
+    assert_eq!(branch("hotfix")["ahead"], 1);
+    assert_eq!(branch("hotfix")["base"], "master");
+    // a base is compared against the other ones
+    assert_eq!(branch("develop")["ahead"], 2);
+    assert_eq!(branch("develop")["base"], "master");
+}
+
+#[test]
+fn mapped_base() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "develop"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "d"]);
+    git(&repo, &["checkout", "-qb", "release/1", "master"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "r"]);
+    git(&repo, &["checkout", "-qb", "hotfix/x", "master"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "h"]);
+    git(&repo, &["config", "bstatus.bases", "develop"]);
+    git(&repo, &["config", "bstatus.hotfix/*.base", "release/*"]);
+
+    let out: serde_json::Value = serde_json::from_str(&bstatus(&repo, &["--json"])).unwrap();
+    let hotfix = out["branches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|b| b["name"] == "hotfix/x")
+        .unwrap();
+    assert_eq!(hotfix["ahead"], 1);
+    assert_eq!(hotfix["base"], "release/1");
+}
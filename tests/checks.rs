@@ -0,0 +1,74 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! The `--fail-if-*` flags turn listings into checks for CI jobs.
+
+mod common;
+
+use common::{bstatus_status, git, init_repo};
+
+#[test]
+fn fail_if_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "hotfix/foo"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "fix"]);
+    git(&repo, &["checkout", "-q", "master"]);
+
+    assert_eq!(
+        1,
+        bstatus_status(&repo, &["-u", "--fail-if-match", "hotfix/"])
+    );
+    assert_eq!(
+        0,
+        bstatus_status(&repo, &["-m", "--fail-if-match", "hotfix/"])
+    );
+    assert_eq!(0, bstatus_status(&repo, &["-u", "hotfix/"]));
+
+    git(&repo, &["merge", "-q", "hotfix/foo"]);
+    assert_eq!(
+        0,
+        bstatus_status(&repo, &["-u", "--fail-if-match", "hotfix/"])
+    );
+}
+
+#[test]
+fn detached_head_is_not_a_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-q", "--detach"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "wip"]);
+
+    assert_eq!(0, bstatus_status(&repo, &["-u", "--fail-if-match"]));
+}
+
+#[test]
+fn fail_if_older_than() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "old"]);
+    std::process::Command::new("git")
+        .current_dir(&repo)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(["commit", "-q", "--allow-empty", "-m", "old"])
+        .env("GIT_COMMITTER_DATE", "2001-01-01T00:00:00")
+        .status()
+        .unwrap();
+    git(&repo, &["checkout", "-q", "master"]);
+
+    assert_eq!(
+        1,
+        bstatus_status(&repo, &["-a", "--fail-if-older-than", "1y"])
+    );
+    assert_eq!(
+        0,
+        bstatus_status(&repo, &["--fail-if-older-than", "1y", "master"])
+    );
+
+    // without -a only the most recent are listed, but the old one still counts
+    for name in &["one", "two", "three", "four", "five"] {
+        git(&repo, &["branch", name]);
+    }
+    assert_eq!(1, bstatus_status(&repo, &["--fail-if-older-than", "1y"]));
+}
@@ -0,0 +1,37 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `--commit-author` narrows the commits listed with `-v` down to someone's.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn hidden_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "feature"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "mine"]);
+    git(
+        &repo,
+        &[
+            "-c",
+            "user.name=Other",
+            "-c",
+            "user.email=other@example.com",
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "theirs",
+        ],
+    );
+
+    let out = bstatus(&repo, &["-v", "--commit-author", "Other", "feature"]);
+    assert!(out.contains(" theirs "), "{}", out);
+    assert!(!out.contains(" mine "), "{}", out);
+    // the commit the branch starts from isn't one of its own, so it's not counted
+    assert!(out.contains("(1 commit by others)"), "{}", out);
+}
@@ -0,0 +1,101 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Helpers for tests which build fixture repositories with the git binary.
+
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub fn git<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> Vec<u8> {
+    let out = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "git: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    out.stdout
+}
+
+/// Run git-bstatus against `repo`, returning its raw stdout.
+pub fn bstatus_raw(repo: &Path, args: &[&str]) -> Vec<u8> {
+    let out = Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--repo")
+        .arg(repo)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    out.stdout
+}
+
+/// Run git-bstatus against `repo`, returning its exit code.
+pub fn bstatus_status(repo: &Path, args: &[&str]) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--repo")
+        .arg(repo)
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+/// Run git-bstatus against `repo`, returning everything about how it went.
+pub fn bstatus_output(repo: &Path, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--repo")
+        .arg(repo)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Run git-bstatus against `repo`, returning its exit code and stderr.
+pub fn bstatus_err(repo: &Path, args: &[&str]) -> (i32, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--repo")
+        .arg(repo)
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        out.status.code().unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    )
+}
+
+pub fn bstatus(repo: &Path, args: &[&str]) -> String {
+    String::from_utf8(bstatus_raw(repo, args)).unwrap()
+}
+
+/// Create a repo with a single commit on master.
+pub fn init_repo(dir: &Path, name: &str) -> PathBuf {
+    let repo = dir.join(name);
+    std::fs::create_dir(&repo).unwrap();
+    git(&repo, &["init", "-q", "-b", "master"]);
+    std::fs::write(repo.join("a"), "a\n").unwrap();
+    git(&repo, &["add", "a"]);
+    git(&repo, &["commit", "-qm", "init"]);
+    repo
+}
+
+/// The line for `branch` in `out`.
+pub fn line<'a>(out: &'a str, branch: &str) -> &'a str {
+    out.lines()
+        .find(|l| l.split_whitespace().any(|w| w == branch))
+        .unwrap_or_else(|| panic!("no {} in {}", branch, out))
+}
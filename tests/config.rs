@@ -0,0 +1,76 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! The bstatus.* settings, how `config --show` lists them, and what happens when they don't make
+//! sense.
+
+mod common;
+
+use common::{bstatus, bstatus_status, git, init_repo};
+use std::process::Command;
+
+#[test]
+fn bad_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+
+    git(&repo, &["config", "bstatus.human-sections", "recent,nope"]);
+    assert_eq!(1, bstatus_status(&repo, &[]));
+
+    git(&repo, &["config", "bstatus.human-sections", "stale"]);
+    git(&repo, &["config", "bstatus.stale-after", "soon"]);
+    assert_eq!(1, bstatus_status(&repo, &[]));
+    git(&repo, &["config", "bstatus.stale-after", "2 weeks"]);
+    assert_eq!(0, bstatus_status(&repo, &[]));
+
+    // read before anything is looked up
+    git(
+        &repo,
+        &["remote", "add", "origin", "https://github.com/o/r.git"],
+    );
+    git(&repo, &["config", "bstatus.forge-cache-ttl", "a while"]);
+    assert_eq!(1, bstatus_status(&repo, &["--prs"]));
+    git(&repo, &["config", "--unset", "bstatus.forge-cache-ttl"]);
+    git(&repo, &["config", "bstatus.forge", "sourcehut"]);
+    assert_eq!(1, bstatus_status(&repo, &["--prs"]));
+    git(&repo, &["config", "--unset", "bstatus.forge"]);
+
+    git(&repo, &["config", "bstatus.issue-pattern", "#(\\d+"]);
+    assert_eq!(1, bstatus_status(&repo, &["--issues"]));
+}
+
+#[test]
+fn show() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["config", "--add", "bstatus.bases", "master"]);
+    git(&repo, &["config", "--add", "bstatus.bases", "release/*"]);
+    git(&repo, &["config", "bstatus.feature/*.base", "master"]);
+    git(&repo, &["config", "bstatus.gitea-token", "secret"]);
+
+    let out = bstatus(&repo, &["config", "--show"]);
+    assert!(
+        out.contains("bstatus.bases master, release/* (local;"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("bstatus.feature/*.base master (local;"),
+        "{}",
+        out
+    );
+    assert!(out.contains("bstatus.stale-after - (unset;"), "{}", out);
+    assert!(out.contains("BSTATUS_LOG - (unset;"), "{}", out);
+    assert!(!out.contains("secret"), "{}", out);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--repo")
+        .arg(&repo)
+        .args(["config", "--show"])
+        .env("BSTATUS_LOG", "trace")
+        .output()
+        .unwrap();
+    let out = String::from_utf8(out.stdout).unwrap();
+    assert!(out.contains("BSTATUS_LOG trace (environment;"), "{}", out);
+}
@@ -0,0 +1,68 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! How the default branch is found when `bstatus.default-base` doesn't say.
+
+mod common;
+
+use common::{bstatus, bstatus_status, git, init_repo};
+use std::path::Path;
+
+/// The default branch, as `config --show` reports it.
+fn default_branch(repo: &Path) -> String {
+    let out = bstatus(repo, &["config", "--show"]);
+    let line = out
+        .lines()
+        .find_map(|l| l.strip_prefix("default branch "))
+        .unwrap_or_else(|| panic!("no default branch in {}", out));
+    line.trim_end_matches(" (detected)").to_string()
+}
+
+#[test]
+fn configured() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["branch", "devel"]);
+    git(&repo, &["config", "bstatus.default-base", "devel"]);
+    assert_eq!(default_branch(&repo), "devel");
+
+    git(&repo, &["config", "bstatus.default-base", "nope"]);
+    assert_eq!(2, bstatus_status(&repo, &[]));
+}
+
+#[test]
+fn remote_head() {
+    let dir = tempfile::tempdir().unwrap();
+    let origin = init_repo(dir.path(), "origin");
+    git(&origin, &["branch", "-qm", "trunk"]);
+    git(dir.path(), &["clone", "-q", "origin", "clone"]);
+    let clone = dir.path().join("clone");
+    git(&clone, &["branch", "master"]);
+    // the remote's HEAD beats anything local
+    git(&clone, &["config", "init.defaultBranch", "master"]);
+    assert_eq!(default_branch(&clone), "trunk");
+}
+
+#[test]
+fn local_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["branch", "-qm", "trunk"]);
+    git(&repo, &["branch", "master"]);
+    git(&repo, &["branch", "devel"]);
+
+    // the branch the repo was created with beats the guess
+    assert_eq!(default_branch(&repo), "trunk");
+
+    // and what new repos default to beats that
+    git(&repo, &["config", "init.defaultBranch", "devel"]);
+    assert_eq!(default_branch(&repo), "devel");
+
+    // without either, it's a guess
+    git(&repo, &["config", "init.defaultBranch", "gone"]);
+    std::fs::remove_dir_all(repo.join(".git/logs")).unwrap();
+    assert_eq!(default_branch(&repo), "master");
+    git(&repo, &["branch", "-qD", "master"]);
+    assert_eq!(2, bstatus_status(&repo, &[]));
+}
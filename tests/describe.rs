@@ -0,0 +1,85 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `describe` edits a branch's description like `git branch --edit-description`.
+
+mod common;
+
+use common::{bstatus_status, git, init_repo};
+use std::path::Path;
+use std::process::Command;
+
+/// Run `describe branch` with `editor` as `GIT_EDITOR`, returning the exit code.
+fn describe(repo: &Path, branch: &str, editor: &str) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--repo")
+        .arg(repo)
+        .args(["describe", branch])
+        .env("GIT_EDITOR", editor)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+/// The description of `branch` according to git, if it has one.
+fn description(repo: &Path, branch: &str) -> Option<String> {
+    let out = Command::new("git")
+        .current_dir(repo)
+        .args(["config", &format!("branch.{}.description", branch)])
+        .output()
+        .unwrap();
+    if out.status.success() {
+        // git config ends what it prints with a newline of its own
+        let out = String::from_utf8(out.stdout).unwrap();
+        Some(out.strip_suffix('\n').unwrap().to_string())
+    } else {
+        None
+    }
+}
+
+#[test]
+fn edit() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["branch", "feature"]);
+
+    // comments are dropped, and git's trailing newline is kept
+    let editor = "printf 'Fixes the thing\\n# not this\\n' >";
+    assert_eq!(0, describe(&repo, "feature", editor));
+    assert_eq!(
+        description(&repo, "feature").as_deref(),
+        Some("Fixes the thing\n")
+    );
+    assert!(!repo.join(".git/EDIT_DESCRIPTION").exists());
+
+    // the editor starts from what's there
+    assert_eq!(
+        0,
+        describe(&repo, "feature", "sed -i 's/the thing/it all/'")
+    );
+    assert_eq!(
+        description(&repo, "feature").as_deref(),
+        Some("Fixes it all\n")
+    );
+
+    // a failed edit changes nothing
+    assert_eq!(5, describe(&repo, "feature", "false"));
+    assert_eq!(
+        description(&repo, "feature").as_deref(),
+        Some("Fixes it all\n")
+    );
+
+    // and emptying it out unsets it
+    assert_eq!(0, describe(&repo, "feature", ": >"));
+    assert_eq!(description(&repo, "feature"), None);
+}
+
+#[test]
+fn missing_branch() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    assert_eq!(2, bstatus_status(&repo, &["describe", "nope"]));
+}
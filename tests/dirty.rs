@@ -0,0 +1,43 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `--dirty` marks the branches whose commits touch files with uncommitted changes.
+
+mod common;
+
+use common::{bstatus, git, init_repo, line};
+
+#[test]
+fn touches_dirty_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "touches-a"]);
+    std::fs::write(repo.join("a"), "b\n").unwrap();
+    git(&repo, &["commit", "-qam", "change a"]);
+    git(&repo, &["checkout", "-qb", "adds-c", "master"]);
+    std::fs::write(repo.join("c"), "c\n").unwrap();
+    git(&repo, &["add", "c"]);
+    git(&repo, &["commit", "-qm", "add c"]);
+    git(&repo, &["checkout", "-q", "master"]);
+
+    // nothing uncommitted yet, and untracked files don't count
+    std::fs::write(repo.join("c"), "untracked\n").unwrap();
+    let out = bstatus(&repo, &["-a", "--dirty"]);
+    assert!(!out.contains("[dirty]"), "{}", out);
+
+    std::fs::write(repo.join("a"), "c\n").unwrap();
+    let out = bstatus(&repo, &["-a", "--dirty"]);
+    assert!(line(&out, "touches-a").contains("[dirty]"), "{}", out);
+    assert!(!line(&out, "adds-c").contains("[dirty]"), "{}", out);
+    assert!(!line(&out, "master").contains("[dirty]"), "{}", out);
+
+    // staged changes count too
+    git(&repo, &["add", "a"]);
+    let out = bstatus(&repo, &["-a", "--dirty"]);
+    assert!(line(&out, "touches-a").contains("[dirty]"), "{}", out);
+
+    // and it's only looked at when asked for
+    let out = bstatus(&repo, &["-a"]);
+    assert!(!out.contains("[dirty]"), "{}", out);
+}
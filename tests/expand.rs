@@ -0,0 +1,70 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `expand` turns `%N` into the Nth branch of whatever was listed last.
+
+mod common;
+
+use common::{bstatus, bstatus_status, git, init_repo};
+
+#[test]
+fn expand_last_listing() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    assert_eq!(2, bstatus_status(&repo, &["expand", "%1"]));
+
+    git(&repo, &["branch", "one"]);
+    git(&repo, &["branch", "two"]);
+
+    bstatus(&repo, &["-a"]);
+    let first = bstatus(&repo, &["expand", "%1"]);
+    let out = bstatus(&repo, &["expand", "switch", "%1", "%2", "%3"]);
+    assert!(out.starts_with(&format!("switch\n{}", first)), "{}", out);
+    for name in &["master\n", "one\n", "two\n"] {
+        assert!(out.contains(name), "{}", out);
+    }
+    assert_eq!(2, bstatus_status(&repo, &["expand", "%4"]));
+
+    // the numbering follows the last listing
+    bstatus(&repo, &["-a", "-r"]);
+    assert_eq!(first, bstatus(&repo, &["expand", "%3"]));
+}
+
+#[test]
+fn expand_tree_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    // by date it's x/two, a then x/one, but the tree puts x/one next to x/two
+    for (name, date) in &[
+        ("x/one", "2021-01-01T00:00:00"),
+        ("a", "2022-01-01T00:00:00"),
+        ("x/two", "2023-01-01T00:00:00"),
+    ] {
+        git(&repo, &["checkout", "-qb", name, "master"]);
+        std::process::Command::new("git")
+            .current_dir(&repo)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["commit", "-q", "--allow-empty", "-m", name])
+            .env("GIT_COMMITTER_DATE", date)
+            .status()
+            .unwrap();
+    }
+    git(&repo, &["checkout", "-q", "master"]);
+
+    bstatus(&repo, &["-a", "--tree"]);
+    let out = bstatus(&repo, &["expand", "%1", "%2", "%3", "%4"]);
+    assert_eq!(out, "master\nx/two\nx/one\na\n");
+
+    // rows are only numbered when asked to, and then it's the numbers to use
+    let out = bstatus(&repo, &["-a", "--tree", "--color=always"]);
+    assert!(!out.contains("[1]"), "{}", out);
+    git(&repo, &["config", "bstatus.numbers", "true"]);
+    let out = bstatus(&repo, &["-a", "--tree"]);
+    assert!(
+        out.lines()
+            .any(|l| l.starts_with("[3]") && l.contains("one")),
+        "{}",
+        out
+    );
+}
@@ -0,0 +1,63 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Forge lookups, against a stand-in for curl which records how it was called.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{git, init_repo};
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+#[test]
+fn token_not_in_argv() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(
+        &repo,
+        &["remote", "add", "origin", "https://github.com/o/r.git"],
+    );
+    git(&repo, &["checkout", "-qb", "feature"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "feature"]);
+
+    let bin = dir.path().join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let curl = bin.join("curl");
+    std::fs::write(
+        &curl,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> {0}/argv\ncat >> {0}/stdin\necho '[]'\n",
+            dir.path().display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&curl, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let out = Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--repo")
+        .arg(&repo)
+        .args(["-a", "--prs"])
+        .env("PATH", path)
+        .env("GH_TOKEN", "sekrit")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let argv = std::fs::read_to_string(dir.path().join("argv")).unwrap();
+    let stdin = std::fs::read_to_string(dir.path().join("stdin")).unwrap();
+    assert!(argv.contains("--header @-"), "{}", argv);
+    assert!(!argv.contains("sekrit"), "{}", argv);
+    assert!(
+        stdin.contains("Authorization: Bearer sekrit\n"),
+        "{}",
+        stdin
+    );
+}
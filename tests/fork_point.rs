@@ -0,0 +1,57 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `--fork-point` sees through rewrites of the base using its reflog, like
+//! `git merge-base --fork-point`.
+
+mod common;
+
+use common::{bstatus, git, init_repo, line};
+
+#[test]
+fn rewritten_upstream() {
+    let dir = tempfile::tempdir().unwrap();
+    let origin = init_repo(dir.path(), "origin");
+    git(dir.path(), &["clone", "-q", "origin", "clone"]);
+    let clone = dir.path().join("clone");
+    // cloning doesn't log where the remote's branches were, but fetching does
+    git(&origin, &["commit", "-q", "--allow-empty", "-m", "base"]);
+    git(&clone, &["fetch", "-q"]);
+    git(&clone, &["checkout", "-qb", "feature", "origin/master"]);
+    git(&clone, &["commit", "-q", "--allow-empty", "-m", "feature"]);
+
+    git(
+        &origin,
+        &["commit", "-q", "--amend", "--allow-empty", "-m", "reworded"],
+    );
+    git(&clone, &["fetch", "-q"]);
+
+    // the old base isn't on master anymore, so it looks like it's the branch's own
+    let out = bstatus(&clone, &["-a"]);
+    assert!(line(&out, "feature").contains("+2"), "{}", out);
+    let out = bstatus(&clone, &["-a", "--fork-point"]);
+    assert!(line(&out, "feature").contains("+1"), "{}", out);
+}
+
+#[test]
+fn rewritten_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "base"]);
+    git(&repo, &["checkout", "-qb", "feature"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "feature"]);
+    git(&repo, &["checkout", "-qb", "merged", "master"]);
+    git(&repo, &["checkout", "-q", "master"]);
+    git(
+        &repo,
+        &["commit", "-q", "--amend", "--allow-empty", "-m", "reworded"],
+    );
+
+    let out = bstatus(&repo, &["-a"]);
+    assert!(line(&out, "feature").contains("+2"), "{}", out);
+    let out = bstatus(&repo, &["-a", "--fork-point"]);
+    assert!(line(&out, "feature").contains("+1"), "{}", out);
+    // a branch already in the base's history is left alone
+    assert!(line(&out, "merged").contains("+0"), "{}", out);
+}
@@ -0,0 +1,73 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! JSON output, for a single repository and aggregated across several.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn single_repo() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "feature"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "feature"]);
+
+    let out: serde_json::Value = serde_json::from_str(&bstatus(&repo, &["--json"])).unwrap();
+    assert_eq!(out["format_version"], 1);
+    assert_eq!(out["default_branch"], "master");
+    assert_eq!(out["merged"], 1);
+    assert_eq!(out["unmerged"], 1);
+    let feature = &out["branches"][0];
+    assert_eq!(feature["name"], "feature");
+    assert_eq!(feature["active"], true);
+    assert_eq!(feature["ahead"], 1);
+    assert!(out.get("detached").is_none());
+
+    // a detached HEAD isn't a branch, so it's kept out of them
+    git(&repo, &["checkout", "-q", "--detach"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "wip"]);
+    let out: serde_json::Value = serde_json::from_str(&bstatus(&repo, &["--json"])).unwrap();
+    assert_eq!(out["branches"].as_array().unwrap().len(), 2);
+    assert_eq!(out["detached"]["active"], true);
+    assert_eq!(out["detached"]["ahead"], 2);
+}
+
+#[test]
+fn recursive() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = init_repo(dir.path(), "a");
+    git(&a, &["branch", "other"]);
+    init_repo(dir.path(), "b");
+
+    let out = std::process::Command::new(env!("CARGO_BIN_EXE_git-bstatus"))
+        .arg("--recursive")
+        .arg(dir.path())
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let out: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let repos = out["repositories"].as_array().unwrap();
+    assert_eq!(repos.len(), 2);
+    assert!(repos[0]["path"].as_str().unwrap().ends_with("a"));
+    assert_eq!(out["format_version"], 1);
+    assert!(repos[0].get("format_version").is_none());
+    assert_eq!(out["totals"]["repositories"], 2);
+    assert_eq!(out["totals"]["branches"], 3);
+}
+
+#[test]
+fn schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    let schema: serde_json::Value = serde_json::from_str(&bstatus(&repo, &["--schema"])).unwrap();
+    // every field we print should be documented
+    let out: serde_json::Value = serde_json::from_str(&bstatus(&repo, &["--json"])).unwrap();
+    let branch = schema["$defs"]["branch"]["properties"].as_object().unwrap();
+    for field in out["branches"][0].as_object().unwrap().keys() {
+        assert!(branch.contains_key(field), "{} missing from schema", field);
+    }
+}
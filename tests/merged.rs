@@ -0,0 +1,52 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `-m`/`-u` can check what made it into any ref, like `git branch --merged`.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn merged_into_ref() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "shipped"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "shipped"]);
+    git(&repo, &["checkout", "-qb", "release-1.0"]);
+    git(&repo, &["checkout", "-qb", "pending", "master"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "pending"]);
+
+    for args in &[
+        &["-m", "release-1.0"][..],
+        &["-mrelease-1.0"],
+        &["--merged=release-1.0"],
+    ] {
+        let out = bstatus(&repo, args);
+        assert!(out.contains("shipped"), "{:?}: {}", args, out);
+        assert!(!out.contains("pending"), "{:?}: {}", args, out);
+    }
+    let out = bstatus(&repo, &["-u", "release-1.0"]);
+    assert!(out.contains("pending"), "{}", out);
+    assert!(!out.contains("shipped"), "{}", out);
+
+    // with a ref given, what's left are patterns
+    let out = bstatus(&repo, &["-m", "release-1.0", "ship"]);
+    assert!(
+        out.contains("shipped") && !out.contains("release-1.0"),
+        "{}",
+        out
+    );
+
+    // and without one, it's a pattern however -u is spelled, even clustered with other flags
+    for args in &[
+        &["-r", "-u", "ship"][..],
+        &["-ru", "ship"],
+        &["--unmerged=ship"],
+    ] {
+        let out = bstatus(&repo, args);
+        assert!(out.contains("shipped"), "{:?}: {}", args, out);
+        assert!(!out.contains("pending"), "{:?}: {}", args, out);
+    }
+}
@@ -0,0 +1,27 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `--metrics` writes gauges for Prometheus' textfile collector.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn every_branch() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    for name in &["one", "two", "three", "four", "five", "six"] {
+        git(&repo, &["branch", name]);
+    }
+
+    // only the most recent are listed, but all of them have gauges
+    let path = dir.path().join("bstatus.prom");
+    bstatus(&repo, &["--metrics", path.to_str().unwrap()]);
+    let out = std::fs::read_to_string(&path).unwrap();
+    let gauges = |name: &str| out.lines().filter(|l| l.starts_with(name)).count();
+    assert_eq!(gauges("git_branch_age_seconds{"), 7, "{}", out);
+    assert_eq!(gauges("git_branch_ahead_commits{"), 7, "{}", out);
+    assert!(out.contains("git_branches_total{"), "{}", out);
+}
@@ -0,0 +1,48 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Branch names aren't necessarily valid UTF-8; they should be escaped for display but otherwise
+//! work like any other branch.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{bstatus, bstatus_raw, git, init_repo};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+#[test]
+fn non_utf8_branch() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(
+        &repo,
+        &[
+            OsStr::new("checkout"),
+            OsStr::new("-qb"),
+            OsStr::from_bytes(b"caf\xe9"),
+        ],
+    );
+    std::fs::write(repo.join("a"), "b\n").unwrap();
+    git(&repo, &["commit", "-qam", "change a"]);
+    git(&repo, &["branch", "-q", "--set-upstream-to", "master"]);
+
+    // the active branch is the non-UTF-8 one, so this also covers the Human mode header
+    let out = bstatus(&repo, &[]);
+    assert!(out.contains("On branch caf\\xe9"), "{}", out);
+
+    for backend in &["libgit2", "cli"] {
+        let out = bstatus(&repo, &["-a", "-v", "--parent", "--backend", backend]);
+        assert!(out.contains("caf\\xe9"), "{}", out);
+        assert!(out.contains("+1"), "{}", out);
+    }
+
+    // names are printed as is so they can be passed back to git
+    let out = bstatus_raw(&repo, &["-n"]);
+    assert!(out.split(|&c| c == b'\n').any(|l| l == b"caf\xe9"));
+    let out = bstatus_raw(&repo, &["-n", "-z"]);
+    assert!(out.ends_with(b"\0") && !out.contains(&b'\n'));
+    assert!(out.split(|&c| c == 0).any(|l| l == b"caf\xe9"));
+}
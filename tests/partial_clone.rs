@@ -0,0 +1,85 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! Scanning a partial clone must work without needing any of the filtered-out objects.
+
+mod common;
+
+use common::{bstatus, bstatus_status, git, init_repo};
+use std::path::Path;
+
+/// Create a partial clone with the given filter, with a local branch one commit ahead of master
+/// and an uncommitted change to a file that branch touches.
+fn partial_clone(dir: &Path, filter: &str) -> std::path::PathBuf {
+    let origin = init_repo(dir, "origin");
+    git(&origin, &["config", "uploadpack.allowFilter", "true"]);
+    git(&origin, &["checkout", "-qb", "feature"]);
+    std::fs::write(origin.join("a"), "b\n").unwrap();
+    git(&origin, &["commit", "-qam", "change a"]);
+    git(&origin, &["checkout", "-q", "master"]);
+
+    let url = format!("file://{}", origin.display());
+    git(
+        dir,
+        &[
+            "clone",
+            "-q",
+            "--no-single-branch",
+            "--filter",
+            filter,
+            &url,
+            "clone",
+        ],
+    );
+    let clone = dir.join("clone");
+    git(
+        &clone,
+        &["branch", "-q", "--no-track", "feature", "origin/feature"],
+    );
+    std::fs::write(clone.join("a"), "c\n").unwrap();
+    clone
+}
+
+fn count_objects(repo: &Path) -> Vec<u8> {
+    git(repo, &["count-objects", "-v"])
+}
+
+#[test]
+fn blobless_clone() {
+    let dir = tempfile::tempdir().unwrap();
+    let clone = partial_clone(dir.path(), "blob:none");
+    let before = count_objects(&clone);
+
+    let out = bstatus(&clone, &["-a", "--dirty"]);
+    assert!(out.contains("+1"), "{}", out);
+    assert!(out.contains("[dirty]"), "{}", out);
+    bstatus(&clone, &["-v"]);
+    let out = bstatus(&clone, &["-a", "--sizes"]);
+    assert!(out.contains("(≥"), "{}", out);
+    let out = bstatus(&clone, &["compare", "feature", "master"]);
+    assert!(
+        out.contains("1 file changed (contents not fetched):\n    a\n"),
+        "{}",
+        out
+    );
+
+    assert_eq!(before, count_objects(&clone));
+}
+
+#[test]
+fn treeless_clone() {
+    let dir = tempfile::tempdir().unwrap();
+    let clone = partial_clone(dir.path(), "tree:0");
+    let before = count_objects(&clone);
+
+    let out = bstatus(&clone, &["-a", "--dirty"]);
+    assert!(out.contains("+1"), "{}", out);
+    bstatus(&clone, &["-v", "--release"]);
+    let out = bstatus(&clone, &["-a", "--sizes"]);
+    assert!(out.contains("(≥"), "{}", out);
+    // feature's files were never fetched, which isn't the same as it not existing
+    assert_eq!(3, bstatus_status(&clone, &["can-switch", "feature"]));
+
+    assert_eq!(before, count_objects(&clone));
+}
@@ -0,0 +1,79 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `restack` moves stacked branches onto their parent's new tip.
+
+mod common;
+
+use common::{bstatus, bstatus_err, git, init_repo};
+use std::path::Path;
+
+fn commit(repo: &Path, file: &str) {
+    std::fs::write(repo.join(file), format!("{}\n", file)).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-qm", file]);
+}
+
+fn count(repo: &Path, range: &str) -> String {
+    let out = git(repo, &["rev-list", "--count", range]);
+    String::from_utf8(out).unwrap().trim().to_string()
+}
+
+#[test]
+fn restack() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["config", "user.name", "Test"]);
+    git(&repo, &["config", "user.email", "test@example.com"]);
+    git(&repo, &["checkout", "-qb", "a"]);
+    commit(&repo, "a1");
+    git(&repo, &["checkout", "-qb", "b"]);
+    commit(&repo, "b1");
+    git(&repo, &["checkout", "-qb", "c"]);
+    commit(&repo, "c1");
+    commit(&repo, "c2");
+    git(&repo, &["checkout", "-q", "a"]);
+    git(&repo, &["commit", "-q", "--amend", "-m", "a1 again"]);
+
+    let out = bstatus(&repo, &["restack", "--dry-run"]);
+    assert_eq!("would rebase b onto a\n", out);
+
+    let out = bstatus(&repo, &["restack"]);
+    assert_eq!("rebasing b onto a\nrebasing c onto b\n", out);
+    assert_eq!("0", count(&repo, "b..a"));
+    assert_eq!("1", count(&repo, "a..b"));
+    assert_eq!("0", count(&repo, "c..b"));
+    assert_eq!("2", count(&repo, "b..c"));
+    let head = git(&repo, &["symbolic-ref", "--short", "HEAD"]);
+    assert_eq!("a\n", String::from_utf8(head).unwrap());
+
+    let out = bstatus(&repo, &["restack"]);
+    assert_eq!("Every stack is up to date.\n", out);
+}
+
+#[test]
+fn restack_stopped() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["config", "user.name", "Test"]);
+    git(&repo, &["config", "user.email", "test@example.com"]);
+    git(&repo, &["checkout", "-qb", "a"]);
+    commit(&repo, "a1");
+    git(&repo, &["checkout", "-qb", "b"]);
+    std::fs::write(repo.join("a1"), "b\n").unwrap();
+    git(&repo, &["commit", "-qam", "b1"]);
+    git(&repo, &["checkout", "-q", "a"]);
+    std::fs::write(repo.join("a1"), "a\n").unwrap();
+    git(&repo, &["commit", "-q", "--amend", "-am", "a1 again"]);
+
+    // git's own explanation comes along
+    let (code, err) = bstatus_err(&repo, &["restack"]);
+    assert_eq!(5, code);
+    assert!(err.contains("rebasing b onto a stopped"), "{}", err);
+    assert!(err.contains("CONFLICT"), "{}", err);
+
+    let (code, err) = bstatus_err(&repo, &["restack"]);
+    assert_eq!(5, code);
+    assert!(err.contains("Rebase in progress"), "{}", err);
+}
@@ -0,0 +1,35 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `--stacks` nests each branch under the closest branch it's built on.
+
+mod common;
+
+use common::{bstatus, git, init_repo};
+
+#[test]
+fn closest_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["branch", "old"]);
+    for i in 0..4 {
+        git(
+            &repo,
+            &["commit", "-q", "--allow-empty", "-m", &format!("m{}", i)],
+        );
+    }
+    // `a` counts its commits from far back, which shouldn't make it look closer to `c` than `b`
+    git(&repo, &["checkout", "-qb", "a", "--track", "old"]);
+    git(&repo, &["reset", "-q", "--hard", "master"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "a"]);
+    git(&repo, &["checkout", "-qb", "b", "--no-track"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "b"]);
+    git(&repo, &["checkout", "-qb", "c", "--no-track"]);
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "c"]);
+
+    let out = bstatus(&repo, &["-a", "--stacks"]);
+    for start in &["  a ", "    b ", "*     c "] {
+        assert!(out.lines().any(|l| l.starts_with(start)), "{}", out);
+    }
+}
@@ -0,0 +1,52 @@
+/* Copyright (C) 2018 Jonathan Lebon <jonathan@jlebon.com>
+ * SPDX-License-Identifier: MIT
+ * */
+
+//! `can-switch` checks whether a branch could be checked out, and says what's in the way.
+
+mod common;
+
+use common::{bstatus_output, git, init_repo};
+
+fn can_switch(repo: &std::path::Path, branch: &str) -> (i32, String) {
+    let out = bstatus_output(repo, &["can-switch", branch]);
+    (
+        out.status.code().unwrap(),
+        String::from_utf8(out.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn can_switch_or_not() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path(), "repo");
+    git(&repo, &["checkout", "-qb", "other"]);
+    std::fs::write(repo.join("a"), "other\n").unwrap();
+    git(&repo, &["commit", "-qam", "other"]);
+    git(&repo, &["checkout", "-q", "master"]);
+
+    let (code, out) = can_switch(&repo, "other");
+    assert_eq!(0, code);
+    assert!(out.contains("Branch other can be checked out."), "{}", out);
+
+    std::fs::write(repo.join("a"), "dirty\n").unwrap();
+    let (code, out) = can_switch(&repo, "other");
+    assert_eq!(1, code);
+    assert!(
+        out.contains("local changes would be overwritten"),
+        "{}",
+        out
+    );
+    assert!(out.contains("    a\n"), "{}", out);
+    git(&repo, &["checkout", "-q", "a"]);
+
+    git(&repo, &["checkout", "-qb", "side"]);
+    std::fs::write(repo.join("b"), "side\n").unwrap();
+    git(&repo, &["add", "b"]);
+    git(&repo, &["commit", "-qm", "side"]);
+    git(&repo, &["checkout", "-q", "master"]);
+    git(&repo, &["merge", "-q", "--no-commit", "--no-ff", "side"]);
+    let (code, out) = can_switch(&repo, "other");
+    assert_eq!(1, code);
+    assert!(out.contains("Merge in progress"), "{}", out);
+}